@@ -1,9 +1,14 @@
 // Copyright 2024 the Kompari Authors
 // SPDX-License-Identifier: Apache-2.0 OR MIT
 
-use clap::Parser;
-use kompari::{CompareConfig, ImageDiff, ReportConfig};
-use std::path::PathBuf;
+use clap::{ArgGroup, Parser, ValueEnum};
+use image::Rgb;
+use kompari::{
+    compare_directories_with, BlessOutcome, CheckResult, ColorDistance, CompareConfig,
+    DiffImageMode, HtmlReportWriter, ImageDiff, Rectangle, ReportConfig, ReportFormat,
+    TerminalGraphics,
+};
+use std::path::{Path, PathBuf};
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
@@ -34,14 +39,131 @@ struct Args {
     #[arg(long, default_value_t = false)]
     ignore_match: bool,
 
-    /// Filter filenames by name
+    /// Compare images in subdirectories as well
+    #[arg(long, short, default_value_t = false)]
+    recursive: bool,
+
+    /// Compare only images matching a pattern, may be used multiple times.
+    /// Patterns without glob metacharacters match any part of the name; patterns with them
+    /// (e.g. 'widgets/**/button_*.png') or prefixed with 'glob:' match the whole name;
+    /// patterns prefixed with 're:' are regular expressions
+    #[arg(long)]
+    filter: Vec<String>,
+
+    /// Skip images matching a pattern, may be used multiple times
+    #[arg(long)]
+    exclude: Vec<String>,
+
+    /// Maximal difference in a color channel for which pixels are considered equal
+    #[arg(long, default_value_t = 0)]
+    pixel_tolerance: u8,
+
+    /// Maximal number of different pixels for which images are considered within tolerance
+    #[arg(long)]
+    max_different_pixels: Option<u64>,
+
+    /// Maximal percentage of different pixels for which images are considered within tolerance
+    #[arg(long)]
+    max_different_pixels_percent: Option<f32>,
+
+    /// Composite images over the given background color (e.g. 'ffffff') before comparison;
+    /// by default the alpha channel is compared as any other channel
+    #[arg(long, value_parser = parse_color)]
+    background: Option<Rgb<u8>>,
+
+    /// Minimal structural similarity (0.0-1.0) for which images are considered within tolerance
+    #[arg(long)]
+    min_ssim: Option<f64>,
+
+    /// Content of the difference image
+    #[arg(long, value_enum, default_value_t = DiffImage::Pixels)]
+    diff_image: DiffImage,
+
+    /// Metric deciding whether two pixels are different
+    #[arg(long, value_enum, default_value_t = Distance::Channel)]
+    color_distance: Distance,
+
+    /// Maximal CIEDE2000 difference for which pixels are considered equal
+    /// (used with '--color-distance delta-e2000')
+    #[arg(long, default_value_t = 0.0)]
+    delta_e_tolerance: f32,
+
+    /// Do not count pixels detected as anti-aliasing as different
+    #[arg(long, default_value_t = false)]
+    detect_antialiasing: bool,
+
+    /// Ignore a region of an image during comparison, may be used multiple times
+    #[arg(long, value_name = "NAME:X,Y,WIDTH,HEIGHT", value_parser = parse_ignore_region)]
+    ignore_region: Vec<(String, Rectangle)>,
+
+    /// Ignore areas given by mask files ('foo.mask.png' for 'foo.png') next to images;
+    /// mask files are then not compared as images
+    #[arg(long, default_value_t = false)]
+    mask_files: bool,
+
+    /// Write difference images into the directory during the comparison;
+    /// by default they are created again from the images when a report needs them
     #[arg(long)]
-    filter: Option<String>,
+    diff_image_dir: Option<PathBuf>,
+
+    /// Number of threads comparing images, all available cores are used by default
+    #[cfg(feature = "parallel")]
+    #[arg(long)]
+    threads: Option<usize>,
 
     #[clap(subcommand)]
     command: Command,
 }
 
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum DiffImage {
+    Pixels,
+    Ssim,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Distance {
+    Channel,
+    DeltaE2000,
+}
+
+fn parse_color(value: &str) -> Result<Rgb<u8>, String> {
+    let value = value.strip_prefix('#').unwrap_or(value);
+    let parse = |i: usize| {
+        value
+            .get(i..i + 2)
+            .and_then(|s| u8::from_str_radix(s, 16).ok())
+    };
+    match (value.len(), parse(0), parse(2), parse(4)) {
+        (6, Some(r), Some(g), Some(b)) => Ok(Rgb([r, g, b])),
+        _ => Err(format!(
+            "Invalid color '{value}', expected hex RGB value like 'ffffff'"
+        )),
+    }
+}
+
+fn parse_ignore_region(value: &str) -> Result<(String, Rectangle), String> {
+    let error = || format!("Invalid region '{value}', expected 'NAME:X,Y,WIDTH,HEIGHT'");
+    let (name, rect) = value.rsplit_once(':').ok_or_else(error)?;
+    let numbers = rect
+        .split(',')
+        .map(|n| n.trim().parse::<u32>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| error())?;
+    match numbers.as_slice() {
+        [x, y, width, height] => Ok((
+            name.to_string(),
+            Rectangle {
+                x: *x,
+                y: *y,
+                width: *width,
+                height: *height,
+            },
+        )),
+        _ => Err(error()),
+    }
+}
+
 #[derive(Parser, Debug)]
 struct ReportArgs {
     /// Output filename, default 'report.html'
@@ -51,39 +173,229 @@ struct ReportArgs {
     /// Embed images into the report
     #[arg(long, default_value_t = false)]
     embed_images: bool,
+
+    /// Report format
+    #[arg(long, value_enum, default_value_t = Format::Html)]
+    format: Format,
+
+    /// Path or URL of the HTML report linked from entries of a JUnit report
+    #[arg(long)]
+    html_report_link: Option<String>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Format {
+    Html,
+    Json,
+    Ndjson,
+    Junit,
+}
+
+#[derive(Parser, Debug)]
+#[command(group(ArgGroup::new("selection").required(true)))]
+struct BlessArgs {
+    /// File with accepted images, one per line (downloaded from the HTML report)
+    #[arg(long, group = "selection")]
+    accept_list: Option<PathBuf>,
+
+    /// Accept all differing images (restricted by '--filter' and '--exclude')
+    #[arg(long, group = "selection", default_value_t = false)]
+    all: bool,
+}
+
+#[derive(Parser, Debug)]
+struct ReviewArgs {
+    /// Graphics used for image previews
+    #[arg(long, value_enum, default_value_t = Graphics::Auto)]
+    graphics: Graphics,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Graphics {
+    Auto,
+    Kitty,
+    HalfBlocks,
 }
 
 #[derive(Parser, Debug)]
 enum Command {
     Report(ReportArgs),
+    /// Check that images match and exit with a status code describing the result:
+    /// 0 = all images match (or are within tolerance), 1 = differences found,
+    /// 3 = missing files, 4 = errors (e.g. images that cannot be loaded),
+    /// 5 = no images compared (e.g. '--filter' matches no names)
+    Check,
+    /// Copy left images over right images, e.g. current test results over snapshots
+    Bless(BlessArgs),
+    /// Review results in the terminal; accepted left images are copied over right images.
+    /// Keys: 'a' accept, 's' skip, 'n' next failure, arrows select, 'q' quit
+    Review(ReviewArgs),
 }
 
-fn process_command(args: Args) -> kompari::Result<()> {
+const EXIT_SUCCESS: i32 = 0;
+
+fn check(image_diff: &ImageDiff) -> i32 {
+    let summary = image_diff.summary();
+    for failure in image_diff.failures() {
+        println!("{failure}");
+    }
+    println!("{summary}");
+    let result = summary.check_result();
+    if result == CheckResult::NothingCompared {
+        eprintln!("Warning: no images were compared");
+    }
+    result.exit_code()
+}
+
+fn bless(image_diff: &ImageDiff, accept_list: Option<&Path>) -> kompari::Result<()> {
+    let outcome = if let Some(accept_list) = accept_list {
+        image_diff.bless_accept_list(accept_list)?
+    } else {
+        BlessOutcome {
+            blessed: image_diff.bless(|_| true)?,
+            skipped: Vec::new(),
+        }
+    };
+    print!("{outcome}");
+    println!("{} image(s) updated", outcome.blessed.len());
+    Ok(())
+}
+
+/// Writes entries of the HTML report while images are compared
+fn write_html_report(
+    config: &CompareConfig,
+    report_config: &ReportConfig,
+    left_path: &Path,
+    right_path: &Path,
+    output: &Path,
+) -> kompari::Result<()> {
+    let mut writer = HtmlReportWriter::create(report_config, output)?;
+    let mut count = 0;
+    compare_directories_with(config, left_path, right_path, |pair_diff| {
+        count += 1;
+        writer.write_entry(&pair_diff)
+    })?;
+    writer.finish(&[(left_path.to_path_buf(), right_path.to_path_buf())])?;
+    if count == 0 {
+        std::fs::remove_file(output)?;
+        println!("Nothing to report");
+    } else {
+        println!(
+            "Report written into '{}'; found {} images",
+            output.display(),
+            count
+        );
+    }
+    Ok(())
+}
+
+/// Returns the exit code of the process
+fn process_command(args: Args) -> kompari::Result<i32> {
     let mut config = CompareConfig::default();
     config.set_ignore_match(args.ignore_match);
     config.set_ignore_left_missing(args.ignore_left_missing);
     config.set_ignore_right_missing(args.ignore_right_missing);
-    config.set_filter_name(args.filter.as_deref());
+    for pattern in &args.filter {
+        config.add_include_pattern(pattern)?;
+    }
+    for pattern in &args.exclude {
+        config.add_exclude_pattern(pattern)?;
+    }
+    config.set_recursive(args.recursive);
+    config.set_pixel_tolerance(args.pixel_tolerance);
+    config.set_max_different_pixels(args.max_different_pixels);
+    config.set_max_different_pixels_percent(args.max_different_pixels_percent);
+    config.set_background(args.background);
+    config.set_min_ssim(args.min_ssim);
+    config.set_diff_image_mode(match args.diff_image {
+        DiffImage::Pixels => DiffImageMode::Pixels,
+        DiffImage::Ssim => DiffImageMode::Ssim,
+    });
+    config.set_color_distance(match args.color_distance {
+        Distance::Channel => ColorDistance::Channel,
+        Distance::DeltaE2000 => ColorDistance::DeltaE2000,
+    });
+    config.set_delta_e_tolerance(args.delta_e_tolerance);
+    config.set_detect_antialiasing(args.detect_antialiasing);
+    for (name, region) in &args.ignore_region {
+        config.add_ignore_region(name, *region);
+    }
+    config.set_use_mask_files(args.mask_files);
+    config.set_diff_image_dir(args.diff_image_dir.clone());
+    #[cfg(feature = "parallel")]
+    config.set_num_threads(args.threads);
 
-    let mut image_diff = ImageDiff::default();
-    image_diff.compare_directories(&config, &args.left_path, &args.right_path)?;
+    let compare = || -> kompari::Result<ImageDiff> {
+        let mut image_diff = ImageDiff::default();
+        image_diff.compare_directories(&config, &args.left_path, &args.right_path)?;
+        Ok(image_diff)
+    };
 
     match args.command {
         Command::Report(opts) => {
+            let mut report_config = ReportConfig::default();
+            report_config.set_left_title(&args.left_title);
+            report_config.set_right_title(&args.right_title);
+            report_config.set_embed_images(opts.embed_images);
+            report_config.set_format(match opts.format {
+                Format::Html => ReportFormat::Html,
+                Format::Json => ReportFormat::Json,
+                Format::Ndjson => ReportFormat::Ndjson,
+                Format::Junit => ReportFormat::Junit,
+            });
+            report_config.set_html_report_link(opts.html_report_link.as_deref());
+            if matches!(opts.format, Format::Html) {
+                write_html_report(
+                    &config,
+                    &report_config,
+                    &args.left_path,
+                    &args.right_path,
+                    &opts.output,
+                )?;
+            } else {
+                compare()?.create_report(&report_config, &opts.output, true)?;
+            }
+            Ok(EXIT_SUCCESS)
+        }
+        Command::Check => Ok(check(&compare()?)),
+        Command::Review(opts) => {
+            let image_diff = compare()?;
             let mut config = ReportConfig::default();
             config.set_left_title(&args.left_title);
             config.set_right_title(&args.right_title);
-            config.set_embed_images(opts.embed_images);
-            image_diff.create_report(&config, &opts.output, true)?;
+            let accepted = image_diff.review(
+                &config,
+                match opts.graphics {
+                    Graphics::Auto => TerminalGraphics::Auto,
+                    Graphics::Kitty => TerminalGraphics::Kitty,
+                    Graphics::HalfBlocks => TerminalGraphics::HalfBlocks,
+                },
+            )?;
+            for title in &accepted {
+                println!("Accepted {title}");
+            }
+            println!("{} image(s) accepted", accepted.len());
+            Ok(EXIT_SUCCESS)
+        }
+        Command::Bless(opts) => {
+            bless(&compare()?, opts.accept_list.as_deref())?;
+            Ok(EXIT_SUCCESS)
         }
     }
-    Ok(())
 }
 
 fn main() {
     let args = Args::parse();
-    if let Err(e) = process_command(args) {
-        eprintln!("Error: {}", e);
-        std::process::exit(1);
+    let is_check = matches!(args.command, Command::Check);
+    match process_command(args) {
+        Ok(code) => std::process::exit(code),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(if is_check {
+                CheckResult::Error.exit_code()
+            } else {
+                1
+            });
+        }
     }
 }
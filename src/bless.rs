@@ -0,0 +1,64 @@
+// Copyright 2024 the Kompari Authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+use crate::difference::PairResult;
+use std::path::Path;
+
+/// Default name of the accept list downloaded from the HTML report
+pub const ACCEPT_LIST_FILENAME: &str = "kompari-accept.txt";
+
+/// Parses an accept list: titles of accepted image pairs, one per line.
+/// Empty lines and lines starting with '#' are skipped.
+pub(crate) fn parse_accept_list(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| line.to_string())
+        .collect()
+}
+
+/// Reads an accept list (as produced by the HTML report) from a file
+pub fn read_accept_list(path: &Path) -> crate::Result<Vec<String>> {
+    Ok(parse_accept_list(&std::fs::read_to_string(path)?))
+}
+
+/// Titles of blessed images and of accepted images that were not blessed
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct BlessOutcome {
+    pub blessed: Vec<String>,
+    /// Accepted titles that were not found or whose images do not differ
+    pub skipped: Vec<String>,
+}
+
+impl std::fmt::Display for BlessOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for title in &self.skipped {
+            writeln!(f, "Skipped {title}: not found or unchanged")?;
+        }
+        for title in &self.blessed {
+            writeln!(f, "Blessed {title}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Replaces the right image of a pair by the left one
+pub(crate) fn bless_pair(pair_diff: &PairResult) -> crate::Result<()> {
+    if let Some(parent) = pair_diff.pair.right.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::copy(&pair_diff.pair.left, &pair_diff.pair.right)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accept_list_skips_comments_and_empty_lines() {
+        let list = "# Accepted images\nfoo.png\n\n  widgets/bar.png  \n";
+        assert_eq!(parse_accept_list(list), vec!["foo.png", "widgets/bar.png"]);
+    }
+}
@@ -0,0 +1,163 @@
+// Copyright 2024 the Kompari Authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Perceptual color difference (CIEDE2000) of sRGB colors.
+
+use image::Rgba;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Lab {
+    pub l: f64,
+    pub a: f64,
+    pub b: f64,
+}
+
+fn srgb_to_linear(value: f64) -> f64 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn lab_f(t: f64) -> f64 {
+    const DELTA: f64 = 6.0 / 29.0;
+    if t > DELTA * DELTA * DELTA {
+        t.cbrt()
+    } else {
+        t / (3.0 * DELTA * DELTA) + 4.0 / 29.0
+    }
+}
+
+impl Lab {
+    /// Converts sRGB color (D65 white point) into CIELAB;
+    /// the color is composited over black by its alpha first.
+    pub fn from_rgba(pixel: &Rgba<u8>) -> Self {
+        let alpha = pixel[3] as f64 / 255.0;
+        let [r, g, b] =
+            [pixel[0], pixel[1], pixel[2]].map(|c| srgb_to_linear(c as f64 / 255.0) * alpha);
+        let x = 0.4124564 * r + 0.3575761 * g + 0.1804375 * b;
+        let y = 0.2126729 * r + 0.7151522 * g + 0.0721750 * b;
+        let z = 0.0193339 * r + 0.1191920 * g + 0.9503041 * b;
+        let (fx, fy, fz) = (lab_f(x / 0.95047), lab_f(y), lab_f(z / 1.08883));
+        Lab {
+            l: 116.0 * fy - 16.0,
+            a: 500.0 * (fx - fy),
+            b: 200.0 * (fy - fz),
+        }
+    }
+}
+
+fn hue_degrees(b: f64, a: f64) -> f64 {
+    if a == 0.0 && b == 0.0 {
+        0.0
+    } else {
+        let h = b.atan2(a).to_degrees();
+        if h < 0.0 {
+            h + 360.0
+        } else {
+            h
+        }
+    }
+}
+
+/// CIEDE2000 color difference with all weighting factors set to 1
+pub(crate) fn delta_e_2000(lab1: &Lab, lab2: &Lab) -> f64 {
+    const POW25_7: f64 = 6103515625.0; // 25^7
+    let c1 = lab1.a.hypot(lab1.b);
+    let c2 = lab2.a.hypot(lab2.b);
+    let c_bar7 = ((c1 + c2) / 2.0).powi(7);
+    let g = 0.5 * (1.0 - (c_bar7 / (c_bar7 + POW25_7)).sqrt());
+    let a1 = (1.0 + g) * lab1.a;
+    let a2 = (1.0 + g) * lab2.a;
+    let c1 = a1.hypot(lab1.b);
+    let c2 = a2.hypot(lab2.b);
+    let h1 = hue_degrees(lab1.b, a1);
+    let h2 = hue_degrees(lab2.b, a2);
+
+    let delta_l = lab2.l - lab1.l;
+    let delta_c = c2 - c1;
+    let delta_h = if c1 * c2 == 0.0 {
+        0.0
+    } else if (h2 - h1).abs() <= 180.0 {
+        h2 - h1
+    } else if h2 - h1 > 180.0 {
+        h2 - h1 - 360.0
+    } else {
+        h2 - h1 + 360.0
+    };
+    let delta_h = 2.0 * (c1 * c2).sqrt() * (delta_h.to_radians() / 2.0).sin();
+
+    let l_bar = (lab1.l + lab2.l) / 2.0;
+    let c_bar = (c1 + c2) / 2.0;
+    let h_bar = if c1 * c2 == 0.0 {
+        h1 + h2
+    } else if (h1 - h2).abs() <= 180.0 {
+        (h1 + h2) / 2.0
+    } else if h1 + h2 < 360.0 {
+        (h1 + h2 + 360.0) / 2.0
+    } else {
+        (h1 + h2 - 360.0) / 2.0
+    };
+
+    let t = 1.0 - 0.17 * (h_bar - 30.0).to_radians().cos()
+        + 0.24 * (2.0 * h_bar).to_radians().cos()
+        + 0.32 * (3.0 * h_bar + 6.0).to_radians().cos()
+        - 0.20 * (4.0 * h_bar - 63.0).to_radians().cos();
+    let delta_theta = 30.0 * (-((h_bar - 275.0) / 25.0).powi(2)).exp();
+    let c_bar7 = c_bar.powi(7);
+    let r_c = 2.0 * (c_bar7 / (c_bar7 + POW25_7)).sqrt();
+    let l50 = (l_bar - 50.0).powi(2);
+    let s_l = 1.0 + 0.015 * l50 / (20.0 + l50).sqrt();
+    let s_c = 1.0 + 0.045 * c_bar;
+    let s_h = 1.0 + 0.015 * c_bar * t;
+    let r_t = -(2.0 * delta_theta).to_radians().sin() * r_c;
+
+    let l = delta_l / s_l;
+    let c = delta_c / s_c;
+    let h = delta_h / s_h;
+    (l * l + c * c + h * h + r_t * c * h).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delta_e_2000() {
+        // Reference values from Sharma, Wu, Dalal: "The CIEDE2000 Color-Difference Formula"
+        let cases = [
+            ((50.0, 2.6772, -79.7751), (50.0, 0.0, -82.7485), 2.0425),
+            ((50.0, 0.0, 0.0), (50.0, -1.0, 2.0), 2.3669),
+            ((50.0, 2.5, 0.0), (73.0, 25.0, -18.0), 27.1492),
+            (
+                (2.0776, 0.0795, -1.1350),
+                (0.9033, -0.0636, -0.5514),
+                0.9082,
+            ),
+        ];
+        for ((l1, a1, b1), (l2, a2, b2), expected) in cases {
+            let lab1 = Lab {
+                l: l1,
+                a: a1,
+                b: b1,
+            };
+            let lab2 = Lab {
+                l: l2,
+                a: a2,
+                b: b2,
+            };
+            assert!((delta_e_2000(&lab1, &lab2) - expected).abs() < 1e-4);
+            assert!((delta_e_2000(&lab2, &lab1) - expected).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_lab_from_rgba() {
+        let white = Lab::from_rgba(&Rgba([255, 255, 255, 255]));
+        assert!((white.l - 100.0).abs() < 1e-3);
+        assert!(white.a.abs() < 1e-2 && white.b.abs() < 1e-2);
+        let black = Lab::from_rgba(&Rgba([255, 255, 255, 0]));
+        assert!(black.l.abs() < 1e-6);
+    }
+}
@@ -1,20 +1,30 @@
 // Copyright 2024 the Kompari Authors
 // SPDX-License-Identifier: Apache-2.0 OR MIT
 
+use crate::color::{delta_e_2000, Lab};
 use crate::difference::ImageInfoResult::Loaded;
+use crate::mask::{apply_ignore_mask, create_ignore_mask, IgnoreMask};
 use crate::pair::Pair;
-use image::{Pixel, Rgb, RgbImage};
+use crate::regions::{bounding_box, find_regions, DifferenceRegion};
+use crate::ssim::{compute_ssim, mean_ssim, SsimMap};
+use crate::{ColorDistance, CompareConfig, DiffImageMode, Rectangle};
+use image::{ImageFormat, Pixel, Rgb, Rgba, RgbaImage};
+use std::borrow::Cow;
 use std::fmt::{Display, Formatter};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-#[derive(Debug)]
-pub(crate) struct Size {
+/// Size of an image in pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Size {
     pub width: u32,
     pub height: u32,
 }
 
+/// Properties of a loaded image
 #[derive(Debug)]
-pub(crate) struct ImageInfo {
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ImageInfo {
     pub size: Size,
 }
 
@@ -31,16 +41,20 @@ impl Display for Size {
 }
 
 impl ImageInfo {
-    pub fn from_image(image: &RgbImage) -> Self {
+    pub fn from_image(image: &RgbaImage) -> Self {
         ImageInfo {
             size: Size::new(image.width(), image.height()),
         }
     }
 }
 
-pub(crate) enum ImageInfoResult {
+/// State of one image of a pair
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ImageInfoResult {
     Loaded(ImageInfo),
     Missing,
+    /// The image cannot be loaded, with the error message
     Error(String),
 }
 
@@ -53,31 +67,357 @@ impl ImageInfoResult {
     }
 }
 
+/// Statistics and visualization of the difference of two images of the same size
 #[derive(Debug)]
-pub(crate) enum Difference {
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ContentDifference {
+    /// Number of pixels where some channel differs by more than the pixel tolerance
+    pub n_different_pixels: u64,
+    /// Number of different pixels detected as anti-aliasing; they are not included
+    /// in `n_different_pixels`
+    pub n_antialiased_pixels: u64,
+    /// Sum of the largest channel differences of all pixels
+    pub distance_sum: u64,
+    /// Mean structural similarity of the images; only computed when it is used,
+    /// see [`CompareConfig::set_min_ssim`] and [`DiffImageMode::Ssim`]
+    pub ssim: Option<f64>,
+    /// Maximal CIEDE2000 color difference of a pixel
+    pub max_delta_e: f64,
+    /// Mean CIEDE2000 color difference over all pixels
+    pub mean_delta_e: f64,
+    /// Area containing all different pixels
+    pub bounding_box: Option<Rectangle>,
+    /// Clusters of different pixels, the largest first
+    pub regions: Vec<DifferenceRegion>,
+    /// Size of the compared images
+    pub size: Size,
+    /// Visualization of the difference, see [`DiffImageMode`]
+    pub diff_image: DiffImage,
+}
+
+/// Difference image of a comparison
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DiffImage {
+    /// Image of compared images in memory, see [`compare_images`](crate::compare_images)
+    InMemory(#[cfg_attr(feature = "serde", serde(with = "crate::serde_png::rgba"))] RgbaImage),
+    /// PNG file written during the comparison, see [`CompareConfig::set_diff_image_dir`]
+    File(PathBuf),
+    /// Image created again from the compared files when it is loaded
+    Deferred(DiffSource),
+}
+
+/// Compared files and settings needed to create a difference image again
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DiffSource {
+    title: String,
+    left: PathBuf,
+    right: PathBuf,
+    ignore_mask: Option<IgnoreMask>,
+    background: Option<[u8; 3]>,
+    pixel_tolerance: u8,
+    diff_image_mode: DiffImageMode,
+    color_distance: ColorDistance,
+    delta_e_tolerance: f32,
+    detect_antialiasing: bool,
+}
+
+impl DiffSource {
+    fn new(config: &CompareConfig, pair: &Pair, ignore_mask: Option<IgnoreMask>) -> Self {
+        DiffSource {
+            title: pair.title.clone(),
+            left: pair.left.clone(),
+            right: pair.right.clone(),
+            ignore_mask,
+            background: config.background.map(|color| color.0),
+            pixel_tolerance: config.pixel_tolerance,
+            diff_image_mode: config.diff_image_mode,
+            color_distance: config.color_distance,
+            delta_e_tolerance: config.delta_e_tolerance,
+            detect_antialiasing: config.detect_antialiasing,
+        }
+    }
+
+    /// Compares the files again, the difference image is the same as in the original
+    /// comparison as long as the files have not changed
+    fn create_image(&self) -> crate::Result<RgbaImage> {
+        let mut config = CompareConfig::default();
+        config.set_background(self.background.map(Rgb));
+        config.set_pixel_tolerance(self.pixel_tolerance);
+        config.set_diff_image_mode(self.diff_image_mode);
+        config.set_color_distance(self.color_distance);
+        config.set_delta_e_tolerance(self.delta_e_tolerance);
+        config.set_detect_antialiasing(self.detect_antialiasing);
+
+        let mut left = load_image(&self.left)?;
+        let mut right = load_image(&self.right)?;
+        if let Some(background) = config.background {
+            composite_over_background(&mut left, background);
+            composite_over_background(&mut right, background);
+        }
+        if let Some(mask) = &self.ignore_mask {
+            apply_ignore_mask(&mask.image()?, &left, &mut right);
+        }
+        match compute_image_diff(&config, &left, &right) {
+            Difference::WithinTolerance(ContentDifference {
+                diff_image: DiffImage::InMemory(image),
+                ..
+            })
+            | Difference::Content(ContentDifference {
+                diff_image: DiffImage::InMemory(image),
+                ..
+            }) => Ok(image),
+            _ => Err(crate::Error::ImagesChanged(self.title.clone())),
+        }
+    }
+}
+
+impl DiffImage {
+    /// The image, loaded from the file or created again if it is not in memory
+    pub fn load(&self) -> crate::Result<Cow<'_, RgbaImage>> {
+        Ok(match self {
+            DiffImage::InMemory(image) => Cow::Borrowed(image),
+            DiffImage::File(path) => Cow::Owned(load_image(path)?),
+            DiffImage::Deferred(source) => Cow::Owned(source.create_image()?),
+        })
+    }
+
+    /// Writes an in-memory image into `dir` and releases its memory
+    fn move_to_dir(&mut self, dir: &Path, title: &str) -> crate::Result<()> {
+        if let DiffImage::InMemory(image) = self {
+            let path = diff_image_path(dir, title);
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            image.save_with_format(&path, ImageFormat::Png)?;
+            *self = DiffImage::File(path);
+        }
+        Ok(())
+    }
+}
+
+/// Suffix appended to titles of images, `a.png` and `a.jpg` get different difference images
+const DIFF_IMAGE_SUFFIX: &str = ".diff.png";
+
+/// Path of the difference image of the pair `title` in `dir`
+pub(crate) fn diff_image_path(dir: &Path, title: &str) -> PathBuf {
+    dir.join(format!("{title}{DIFF_IMAGE_SUFFIX}"))
+}
+
+/// Result of comparing a pair of images
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Difference {
+    /// Images are identical
     None,
+    /// One of the images is missing
+    MissingFile,
+    /// One of the images (or its mask) cannot be loaded, with the error message
+    LoadError(String),
+    SizeMismatch,
+    /// Images differ, but the difference is within the configured tolerance
+    WithinTolerance(ContentDifference),
+    Content(ContentDifference),
+}
+
+/// Kind of a [`Difference`] without its details
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DifferenceKind {
+    Match,
     MissingFile,
     LoadError,
     SizeMismatch,
-    Content {
-        n_different_pixels: u64,
-        distance_sum: u64,
-        diff_image: RgbImage,
-    },
+    WithinTolerance,
+    Content,
 }
 
-pub(crate) struct PairResult {
-    pub pair: Pair,
-    pub difference: Difference,
-    pub left_info: ImageInfoResult,
-    pub right_info: ImageInfoResult,
+impl ContentDifference {
+    /// Short human readable statistics of the difference
+    pub fn stats_summary(&self) -> String {
+        let Size { width, height } = self.size;
+        let pct = self.n_different_pixels as f64 / (width as f64 * height as f64) * 100.0;
+        let mut summary = format!("{} different pixels ({pct:.2}%)", self.n_different_pixels);
+        if let Some(ssim) = self.ssim {
+            summary.push_str(&format!(", SSIM {ssim:.4}"));
+        }
+        summary.push_str(&format!(", max ΔE {:.2}", self.max_delta_e));
+        if self.n_antialiased_pixels > 0 {
+            summary.push_str(&format!(
+                ", {} anti-aliased pixels",
+                self.n_antialiased_pixels
+            ));
+        }
+        summary
+    }
 }
 
-fn load_image(path: &Path) -> crate::Result<RgbImage> {
-    Ok(image::ImageReader::open(path)?.decode()?.into_rgb8())
+impl Difference {
+    pub fn status(&self) -> &'static str {
+        match self {
+            Difference::None => "Match",
+            Difference::MissingFile => "Missing file",
+            Difference::LoadError(_) => "Loading error",
+            Difference::SizeMismatch => "Size mismatch",
+            Difference::WithinTolerance(_) => "Within tolerance",
+            Difference::Content(_) => "Different content",
+        }
+    }
+
+    pub fn kind(&self) -> DifferenceKind {
+        match self {
+            Difference::None => DifferenceKind::Match,
+            Difference::MissingFile => DifferenceKind::MissingFile,
+            Difference::LoadError(_) => DifferenceKind::LoadError,
+            Difference::SizeMismatch => DifferenceKind::SizeMismatch,
+            Difference::WithinTolerance(_) => DifferenceKind::WithinTolerance,
+            Difference::Content(_) => DifferenceKind::Content,
+        }
+    }
+
+    /// Statistics of images that differ in content
+    pub fn content(&self) -> Option<&ContentDifference> {
+        match self {
+            Difference::WithinTolerance(content) | Difference::Content(content) => Some(content),
+            _ => None,
+        }
+    }
+
+    /// Images neither match nor are within tolerance
+    pub fn is_failure(&self) -> bool {
+        !matches!(self, Difference::None | Difference::WithinTolerance(_))
+    }
+}
+
+/// Result of comparing a pair of images with the same name
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PairResult {
+    pub(crate) pair: Pair,
+    pub(crate) difference: Difference,
+    pub(crate) left_info: ImageInfoResult,
+    pub(crate) right_info: ImageInfoResult,
+    /// Areas ignored during the comparison
+    pub(crate) ignore_mask: Option<IgnoreMask>,
+}
+
+impl PairResult {
+    /// Name of the images, relative paths are separated by '/'
+    pub fn title(&self) -> &str {
+        &self.pair.title
+    }
+
+    pub fn left_path(&self) -> &Path {
+        &self.pair.left
+    }
+
+    pub fn right_path(&self) -> &Path {
+        &self.pair.right
+    }
+
+    /// Human readable status, e.g. "Different content"
+    pub fn status(&self) -> &'static str {
+        self.difference.status()
+    }
+
+    pub fn difference(&self) -> &Difference {
+        &self.difference
+    }
+
+    pub fn left_info(&self) -> &ImageInfoResult {
+        &self.left_info
+    }
+
+    pub fn right_info(&self) -> &ImageInfoResult {
+        &self.right_info
+    }
+
+    /// Areas ignored during the comparison, if any
+    pub fn ignore_mask(&self) -> Option<&IgnoreMask> {
+        self.ignore_mask.as_ref()
+    }
+
+    /// Whether the left image may replace the right one,
+    /// i.e. the left image is loaded and the images do not match
+    pub fn is_blessable(&self) -> bool {
+        self.left_info.info().is_some() && !matches!(self.difference, Difference::None)
+    }
+
+    /// Replaces an in-memory difference image by its description, so that only
+    /// statistics are kept when results are collected
+    pub(crate) fn release_diff_image(&mut self, config: &CompareConfig) {
+        if let Difference::WithinTolerance(content) | Difference::Content(content) =
+            &mut self.difference
+        {
+            if matches!(content.diff_image, DiffImage::InMemory(_)) {
+                content.diff_image = DiffImage::Deferred(DiffSource::new(
+                    config,
+                    &self.pair,
+                    self.ignore_mask.clone(),
+                ));
+            }
+        }
+    }
+
+    /// One line description of the result, e.g. "foo.png: Different content (...)"
+    pub fn description(&self) -> String {
+        let status = self.difference.status();
+        match &self.difference {
+            Difference::WithinTolerance(content) | Difference::Content(content) => {
+                format!(
+                    "{}: {status} ({})",
+                    self.pair.title,
+                    content.stats_summary()
+                )
+            }
+            Difference::SizeMismatch => match (self.left_info.info(), self.right_info.info()) {
+                (Some(left), Some(right)) => {
+                    format!(
+                        "{}: {status} ({} vs {})",
+                        self.pair.title, left.size, right.size
+                    )
+                }
+                _ => format!("{}: {status}", self.pair.title),
+            },
+            Difference::MissingFile => {
+                let side = if matches!(self.left_info, ImageInfoResult::Missing) {
+                    self.pair.left.display()
+                } else {
+                    self.pair.right.display()
+                };
+                format!("{}: {status} ({side})", self.pair.title)
+            }
+            Difference::LoadError(error) => format!("{}: {status} ({error})", self.pair.title),
+            Difference::None => format!("{}: {status}", self.pair.title),
+        }
+    }
+}
+
+pub(crate) fn load_image(path: &Path) -> crate::Result<RgbaImage> {
+    Ok(image::ImageReader::open(path)?.decode()?.into_rgba8())
 }
 
-fn load_image_with_info(path: &Path) -> (Option<RgbImage>, ImageInfoResult) {
+/// Composite image over an opaque background color, the result is fully opaque
+fn composite_over_background(image: &mut RgbaImage, background: Rgb<u8>) {
+    for pixel in image.pixels_mut() {
+        let alpha = pixel[3] as u32;
+        for (c, b) in pixel.0[..3].iter_mut().zip(background.0) {
+            *c = ((*c as u32 * alpha + b as u32 * (255 - alpha) + 127) / 255) as u8;
+        }
+        pixel[3] = 255;
+    }
+}
+
+/// Error message followed by the message of its source
+fn error_message(error: &crate::Error) -> String {
+    match std::error::Error::source(error) {
+        Some(source) => format!("{error}: {source}"),
+        None => error.to_string(),
+    }
+}
+
+fn load_image_with_info(path: &Path) -> (Option<RgbaImage>, ImageInfoResult) {
     if !path.exists() {
         return (None, ImageInfoResult::Missing);
     }
@@ -86,100 +426,685 @@ fn load_image_with_info(path: &Path) -> (Option<RgbImage>, ImageInfoResult) {
             let info = ImageInfo::from_image(&image);
             (Some(image), ImageInfoResult::Loaded(info))
         }
-        Err(e) => (None, ImageInfoResult::Error(e.to_string())),
+        Err(e) => (None, ImageInfoResult::Error(error_message(&e))),
     }
 }
 
-fn compute_pair_diff(pair: &Pair) -> (Difference, ImageInfoResult, ImageInfoResult) {
+pub(crate) fn compute_pair_diff(config: &CompareConfig, pair: Pair) -> crate::Result<PairResult> {
     let (left, left_info) = load_image_with_info(&pair.left);
     let (right, right_info) = load_image_with_info(&pair.right);
 
-    let (left, right) = match (left, right) {
+    let (mut left, mut right) = match (left, right) {
         (Some(left), Some(right)) => (left, right),
         _ => {
-            return (
-                match (&left_info, &right_info) {
-                    (_, ImageInfoResult::Error(_)) | (ImageInfoResult::Error(_), _) => {
-                        Difference::LoadError
-                    }
-                    (_, ImageInfoResult::Missing) | (ImageInfoResult::Missing, _) => {
-                        Difference::MissingFile
-                    }
-                    _ => unreachable!(),
-                },
+            let difference = match (&left_info, &right_info) {
+                (ImageInfoResult::Error(error), _) => {
+                    Difference::LoadError(format!("{}: {error}", pair.left.display()))
+                }
+                (_, ImageInfoResult::Error(error)) => {
+                    Difference::LoadError(format!("{}: {error}", pair.right.display()))
+                }
+                (_, ImageInfoResult::Missing) | (ImageInfoResult::Missing, _) => {
+                    Difference::MissingFile
+                }
+                _ => unreachable!(),
+            };
+            return Ok(PairResult {
+                pair,
+                difference,
                 left_info,
                 right_info,
-            )
+                ignore_mask: None,
+            });
         }
     };
 
-    if left.width() != right.width() || left.height() != right.height() {
-        return (Difference::SizeMismatch, left_info, right_info);
+    if let Some(background) = config.background {
+        composite_over_background(&mut left, background);
+        composite_over_background(&mut right, background);
     }
 
-    let n_different_pixels: u64 = left
-        .pixels()
-        .zip(right.pixels())
-        .map(|(p1, p2)| if p1 != p2 { 1 } else { 0 })
-        .sum();
+    let mut ignore_mask = None;
+    let mut difference = if left.dimensions() != right.dimensions() {
+        Difference::SizeMismatch
+    } else {
+        let mask = create_ignore_mask(config, &pair, left.width(), left.height());
+        match mask.as_ref().map(IgnoreMask::image).transpose() {
+            Ok(mask_image) => {
+                if let Some(mask_image) = &mask_image {
+                    apply_ignore_mask(mask_image, &left, &mut right);
+                }
+                ignore_mask = mask;
+                compute_image_diff(config, &left, &right)
+            }
+            Err(error) => {
+                let paths = mask.iter().flat_map(IgnoreMask::mask_files);
+                let paths: Vec<_> = paths.map(|path| path.display().to_string()).collect();
+                Difference::LoadError(format!(
+                    "Mask {}: {}",
+                    paths.join(", "),
+                    error_message(&error)
+                ))
+            }
+        }
+    };
+    if let (Some(dir), Difference::WithinTolerance(content) | Difference::Content(content)) =
+        (&config.diff_image_dir, &mut difference)
+    {
+        content.diff_image.move_to_dir(dir, &pair.title)?;
+    }
+    Ok(PairResult {
+        pair,
+        difference,
+        left_info,
+        right_info,
+        ignore_mask,
+    })
+}
+
+/// Compares images already in memory; ignore masks are not used as they
+/// are bound to image files
+pub(crate) fn compare_rgba_images(
+    config: &CompareConfig,
+    mut left: RgbaImage,
+    mut right: RgbaImage,
+) -> Difference {
+    if let Some(background) = config.background {
+        composite_over_background(&mut left, background);
+        composite_over_background(&mut right, background);
+    }
+    compute_image_diff(config, &left, &right)
+}
+
+/// Scale of CIEDE2000 difference into diff image intensity; difference 50 is rendered
+/// with the full intensity
+const DELTA_E_INTENSITY_SCALE: f64 = 255.0 / 50.0;
 
-    if n_different_pixels == 0 {
-        return (Difference::None, left_info, right_info);
+fn compute_image_diff(config: &CompareConfig, left: &RgbaImage, right: &RgbaImage) -> Difference {
+    if left.width() != right.width() || left.height() != right.height() {
+        return Difference::SizeMismatch;
     }
 
+    if left == right {
+        return Difference::None;
+    }
+
+    let mut n_different_pixels: u64 = 0;
+    let mut n_antialiased_pixels: u64 = 0;
     let mut distance_sum: u64 = 0;
+    let mut max_delta_e: f64 = 0.0;
+    let mut delta_e_sum: f64 = 0.0;
+    let width = left.width() as usize;
+    let mut different_mask = vec![false; width * left.height() as usize];
 
     let diff_image_data: Vec<u8> = left
         .pixels()
         .zip(right.pixels())
-        .flat_map(|(p1, p2)| {
-            let (abs_v, v) = compute_distance(p1, p2);
+        .enumerate()
+        .flat_map(|(index, (p1, p2))| {
+            let (abs_v, v, channel) = compute_distance(p1, p2);
             distance_sum += abs_v as u64;
-            if v < 0 {
-                [abs_v as u8, 0, 0]
+            let (delta_e, lightness_change) = if p1 == p2 {
+                (0.0, 0.0)
             } else {
-                [0, abs_v as u8, 0]
+                let (lab1, lab2) = (Lab::from_rgba(p1), Lab::from_rgba(p2));
+                (delta_e_2000(&lab1, &lab2), lab2.l - lab1.l)
+            };
+            max_delta_e = max_delta_e.max(delta_e);
+            delta_e_sum += delta_e;
+            let is_different = match config.color_distance {
+                ColorDistance::Channel => abs_v > config.pixel_tolerance as i32,
+                ColorDistance::DeltaE2000 => delta_e > config.delta_e_tolerance as f64,
+            };
+            if is_different {
+                let (x, y) = ((index % width) as u32, (index / width) as u32);
+                if config.detect_antialiasing && is_antialiased_pixel(left, right, x, y) {
+                    n_antialiased_pixels += 1;
+                    return ANTIALIASING_COLOR;
+                }
+                n_different_pixels += 1;
+                different_mask[index] = true;
+            }
+            match config.color_distance {
+                ColorDistance::Channel => {
+                    if abs_v == 0 {
+                        [0, 0, 0, 255]
+                    } else if channel == 3 {
+                        [0, 0, abs_v as u8, 255]
+                    } else if v < 0 {
+                        [abs_v as u8, 0, 0, 255]
+                    } else {
+                        [0, abs_v as u8, 0, 255]
+                    }
+                }
+                ColorDistance::DeltaE2000 => {
+                    let value = (delta_e * DELTA_E_INTENSITY_SCALE).min(255.0) as u8;
+                    if lightness_change < 0.0 {
+                        [value, 0, 0, 255]
+                    } else {
+                        [0, value, 0, 255]
+                    }
+                }
             }
         })
         .collect();
-    let diff_image = RgbImage::from_vec(left.width(), left.height(), diff_image_data).unwrap();
+    let (ssim, diff_image) = match config.diff_image_mode {
+        DiffImageMode::Pixels => (
+            config.min_ssim.map(|_| mean_ssim(left, right)),
+            RgbaImage::from_vec(left.width(), left.height(), diff_image_data).unwrap(),
+        ),
+        DiffImageMode::Ssim => {
+            let (ssim, ssim_map) = compute_ssim(left, right);
+            (Some(ssim), ssim_diff_image(&ssim_map))
+        }
+    };
+    let n_pixels = left.width() as u64 * left.height() as u64;
+    let regions = find_regions(&different_mask, left.width(), left.height());
+    let content = ContentDifference {
+        bounding_box: bounding_box(&regions),
+        regions,
+        n_different_pixels,
+        n_antialiased_pixels,
+        distance_sum,
+        ssim,
+        max_delta_e,
+        mean_delta_e: delta_e_sum / n_pixels as f64,
+        size: Size::new(left.width(), left.height()),
+        diff_image: DiffImage::InMemory(diff_image),
+    };
+    if config.is_within_tolerance(&content, n_pixels) {
+        Difference::WithinTolerance(content)
+    } else {
+        Difference::Content(content)
+    }
+}
+
+/// Color of anti-aliased pixels in the difference image
+const ANTIALIASING_COLOR: [u8; 4] = [255, 255, 0, 255];
+
+/// Brightness (luma) of a pixel blended with white by its alpha
+fn brightness(pixel: &Rgba<u8>) -> f64 {
+    let [r, g, b, a] = pixel.0.map(|c| c as f64);
+    let blend = |c: f64| 255.0 + (c - 255.0) * a / 255.0;
+    blend(r) * 0.29889531 + blend(g) * 0.58662247 + blend(b) * 0.11448223
+}
+
+/// Bounds of the 3x3 neighborhood of a pixel clipped to the image
+fn neighborhood(image: &RgbaImage, x: u32, y: u32) -> (u32, u32, u32, u32) {
     (
-        Difference::Content {
-            n_different_pixels,
-            distance_sum,
-            diff_image,
-        },
-        left_info,
-        right_info,
+        x.saturating_sub(1),
+        y.saturating_sub(1),
+        (x + 1).min(image.width() - 1),
+        (y + 1).min(image.height() - 1),
     )
 }
 
-fn compute_distance(p1: &Rgb<u8>, p2: &Rgb<u8>) -> (i32, i32) {
-    p1.channels()
-        .iter()
-        .zip(p2.channels())
-        .fold((0, 0), |(abs_v, v), (c1, c2)| {
+/// Checks if a pixel has at least three adjacent pixels of the same color
+fn has_many_siblings(image: &RgbaImage, x: u32, y: u32) -> bool {
+    let (x0, y0, x2, y2) = neighborhood(image, x, y);
+    let pixel = image.get_pixel(x, y);
+    let mut zeroes = if x == x0 || x == x2 || y == y0 || y == y2 {
+        1
+    } else {
+        0
+    };
+    for nx in x0..=x2 {
+        for ny in y0..=y2 {
+            if (nx != x || ny != y) && image.get_pixel(nx, ny) == pixel {
+                zeroes += 1;
+                if zeroes > 2 {
+                    return true;
+                }
+            }
+        }
+    }
+    false
+}
+
+/// Checks if a pixel is likely anti-aliasing in `image` compared to `other`.
+///
+/// The pixel has to lie on a brightness gradient between its darkest and brightest neighbor
+/// and one of these neighbors has to be part of a flat area in both images.
+/// (Based on "Anti-aliased Pixel and Intensity Slope Detector" by V. Vysniauskas, 2009,
+/// as used in pixelmatch)
+fn is_antialiased(image: &RgbaImage, other: &RgbaImage, x: u32, y: u32) -> bool {
+    let (x0, y0, x2, y2) = neighborhood(image, x, y);
+    let center = brightness(image.get_pixel(x, y));
+    let mut zeroes = if x == x0 || x == x2 || y == y0 || y == y2 {
+        1
+    } else {
+        0
+    };
+    let (mut min, mut max) = (0.0, 0.0);
+    let (mut min_pos, mut max_pos) = ((0, 0), (0, 0));
+    for nx in x0..=x2 {
+        for ny in y0..=y2 {
+            if nx == x && ny == y {
+                continue;
+            }
+            let delta = center - brightness(image.get_pixel(nx, ny));
+            if delta == 0.0 {
+                zeroes += 1;
+                if zeroes > 2 {
+                    return false;
+                }
+            } else if delta < min {
+                min = delta;
+                min_pos = (nx, ny);
+            } else if delta > max {
+                max = delta;
+                max_pos = (nx, ny);
+            }
+        }
+    }
+    if min == 0.0 || max == 0.0 {
+        return false;
+    }
+    (has_many_siblings(image, min_pos.0, min_pos.1)
+        && has_many_siblings(other, min_pos.0, min_pos.1))
+        || (has_many_siblings(image, max_pos.0, max_pos.1)
+            && has_many_siblings(other, max_pos.0, max_pos.1))
+}
+
+fn is_antialiased_pixel(left: &RgbaImage, right: &RgbaImage, x: u32, y: u32) -> bool {
+    is_antialiased(left, right, x, y) || is_antialiased(right, left, x, y)
+}
+
+/// Renders structural dissimilarity, identical areas are black
+fn ssim_diff_image(ssim_map: &SsimMap) -> RgbaImage {
+    RgbaImage::from_fn(ssim_map.width(), ssim_map.height(), |x, y| {
+        let value = ((1.0 - ssim_map.get_pixel(x, y)[0]).clamp(0.0, 1.0) * 255.0) as u8;
+        Rgba([value, 0, value, 255])
+    })
+}
+
+/// Returns the absolute and signed value of the largest channel difference
+/// and the index of the channel where it occurred
+fn compute_distance(p1: &Rgba<u8>, p2: &Rgba<u8>) -> (i32, i32, usize) {
+    p1.channels().iter().zip(p2.channels()).enumerate().fold(
+        (0, 0, 0),
+        |(abs_v, v, channel), (new_channel, (c1, c2))| {
             let new = (*c2 as i32) - (*c1 as i32);
             let abs_new = new.abs();
             if abs_new > abs_v {
-                (abs_new, new)
+                (abs_new, new, new_channel)
             } else {
-                (abs_v, v)
+                (abs_v, v, channel)
             }
-        })
+        },
+    )
 }
 
-pub(crate) fn compute_differences(pairs: Vec<Pair>) -> Vec<PairResult> {
-    pairs
-        .into_iter()
-        .map(|pair| {
-            let (difference, left_info, right_info) = compute_pair_diff(&pair);
-            PairResult {
-                pair,
-                difference,
-                left_info,
-                right_info,
+/// Passes results to `f` in the order of pairs
+#[cfg(not(feature = "parallel"))]
+pub(crate) fn for_each_difference(
+    config: &CompareConfig,
+    pairs: Vec<Pair>,
+    mut f: impl FnMut(PairResult) -> crate::Result<()>,
+) -> crate::Result<()> {
+    for pair in pairs {
+        f(compute_pair_diff(config, pair)?)?;
+    }
+    Ok(())
+}
+
+/// Pairs are compared in a thread pool, a chunk of pairs at a time, so that only results
+/// of one chunk are kept in memory; results are passed to `f` in the order of pairs
+#[cfg(feature = "parallel")]
+pub(crate) fn for_each_difference(
+    config: &CompareConfig,
+    pairs: Vec<Pair>,
+    mut f: impl FnMut(PairResult) -> crate::Result<()>,
+) -> crate::Result<()> {
+    use rayon::prelude::*;
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(config.num_threads.unwrap_or(0))
+        .build()
+        .map_err(|e| crate::Error::ThreadPoolError(e.to_string()))?;
+    let chunk_size = pool.current_num_threads() * 4;
+    let mut pairs = pairs.into_iter().peekable();
+    while pairs.peek().is_some() {
+        let chunk: Vec<Pair> = pairs.by_ref().take(chunk_size).collect();
+        let results: Vec<PairResult> = pool.install(|| {
+            chunk
+                .into_par_iter()
+                .map(|pair| compute_pair_diff(config, pair))
+                .collect::<crate::Result<_>>()
+        })?;
+        for result in results {
+            f(result)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image_with_pixel(value: Rgba<u8>) -> RgbaImage {
+        RgbaImage::from_fn(10, 10, |x, y| {
+            if x == 3 && y == 4 {
+                value
+            } else {
+                Rgba([100, 100, 100, 255])
+            }
+        })
+    }
+
+    #[test]
+    fn test_pixel_tolerance() {
+        let left = image_with_pixel(Rgba([100, 100, 100, 255]));
+        let right = image_with_pixel(Rgba([101, 99, 100, 255]));
+        let mut config = CompareConfig::default();
+        assert!(matches!(
+            compute_image_diff(&config, &left, &left),
+            Difference::None
+        ));
+        assert!(matches!(
+            compute_image_diff(&config, &left, &right),
+            Difference::Content(ContentDifference {
+                n_different_pixels: 1,
+                ..
+            })
+        ));
+        config.set_pixel_tolerance(1);
+        assert!(matches!(
+            compute_image_diff(&config, &left, &right),
+            Difference::WithinTolerance(ContentDifference {
+                n_different_pixels: 0,
+                distance_sum: 1,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_max_different_pixels() {
+        let left = image_with_pixel(Rgba([100, 100, 100, 255]));
+        let right = image_with_pixel(Rgba([0, 0, 0, 255]));
+        let mut config = CompareConfig::default();
+        config.set_max_different_pixels(Some(1));
+        assert!(matches!(
+            compute_image_diff(&config, &left, &right),
+            Difference::WithinTolerance(_)
+        ));
+        config.set_max_different_pixels_percent(Some(0.5));
+        assert!(matches!(
+            compute_image_diff(&config, &left, &right),
+            Difference::Content(_)
+        ));
+        config.set_max_different_pixels(None);
+        config.set_max_different_pixels_percent(Some(1.0));
+        assert!(matches!(
+            compute_image_diff(&config, &left, &right),
+            Difference::WithinTolerance(_)
+        ));
+    }
+
+    #[test]
+    fn test_alpha_difference() {
+        let left = image_with_pixel(Rgba([100, 100, 100, 255]));
+        let right = image_with_pixel(Rgba([100, 100, 100, 0]));
+        let config = CompareConfig::default();
+        let Difference::Content(content) = compute_image_diff(&config, &left, &right) else {
+            panic!("Alpha difference not detected");
+        };
+        assert_eq!(content.n_different_pixels, 1);
+        assert_eq!(
+            content.diff_image.load().unwrap().get_pixel(3, 4),
+            &Rgba([0, 0, 255, 255])
+        );
+        assert_eq!(
+            content.bounding_box,
+            Some(Rectangle {
+                x: 3,
+                y: 4,
+                width: 1,
+                height: 1
+            })
+        );
+        assert_eq!(content.regions.len(), 1);
+
+        let mut left = left;
+        let mut right = right;
+        composite_over_background(&mut left, Rgb([100, 100, 100]));
+        composite_over_background(&mut right, Rgb([100, 100, 100]));
+        assert!(matches!(
+            compute_image_diff(&config, &left, &right),
+            Difference::None
+        ));
+    }
+
+    #[test]
+    fn test_min_ssim() {
+        let left = image_with_pixel(Rgba([100, 100, 100, 255]));
+        let right = image_with_pixel(Rgba([90, 90, 90, 255]));
+        let mut config = CompareConfig::default();
+        config.set_min_ssim(Some(0.9));
+        assert!(matches!(
+            compute_image_diff(&config, &left, &right),
+            Difference::WithinTolerance(_)
+        ));
+        config.set_min_ssim(Some(0.9999));
+        assert!(matches!(
+            compute_image_diff(&config, &left, &right),
+            Difference::Content(_)
+        ));
+    }
+
+    #[test]
+    fn ssim_computed_only_when_used() {
+        let left = image_with_pixel(Rgba([100, 100, 100, 255]));
+        let right = image_with_pixel(Rgba([90, 90, 90, 255]));
+        let ssim = |config: &CompareConfig| {
+            compute_image_diff(config, &left, &right)
+                .content()
+                .unwrap()
+                .ssim
+        };
+        let mut config = CompareConfig::default();
+        assert_eq!(ssim(&config), None);
+        config.set_diff_image_mode(DiffImageMode::Ssim);
+        let with_map = ssim(&config).unwrap();
+        config.set_diff_image_mode(DiffImageMode::Pixels);
+        config.set_min_ssim(Some(0.5));
+        assert_eq!(ssim(&config), Some(with_map));
+    }
+
+    #[test]
+    fn test_min_ssim_with_hue_change() {
+        // Red and green with the same luminance
+        let left = RgbaImage::from_pixel(10, 10, Rgba([255, 0, 0, 255]));
+        let right = RgbaImage::from_fn(10, 10, |x, _| {
+            if x < 5 {
+                Rgba([255, 0, 0, 255])
+            } else {
+                Rgba([0, 130, 0, 255])
             }
+        });
+        let mut config = CompareConfig::default();
+        config.set_min_ssim(Some(0.9));
+        assert!(matches!(
+            compute_image_diff(&config, &left, &right),
+            Difference::Content(_)
+        ));
+    }
+
+    #[test]
+    fn test_delta_e_tolerance() {
+        let left = image_with_pixel(Rgba([100, 100, 100, 255]));
+        let right = image_with_pixel(Rgba([100, 100, 104, 255]));
+        let mut config = CompareConfig::default();
+        config.set_color_distance(ColorDistance::DeltaE2000);
+        let Difference::Content(content) = compute_image_diff(&config, &left, &right) else {
+            panic!("Difference not detected");
+        };
+        assert_eq!(content.n_different_pixels, 1);
+        assert!(content.max_delta_e > 0.5 && content.max_delta_e < 5.0);
+        assert!((content.mean_delta_e - content.max_delta_e / 100.0).abs() < 1e-9);
+        config.set_delta_e_tolerance(5.0);
+        assert!(matches!(
+            compute_image_diff(&config, &left, &right),
+            Difference::WithinTolerance(_)
+        ));
+    }
+
+    #[test]
+    fn test_antialiasing_detection() {
+        // Vertical edge between black and white, the right image has a gray edge pixel
+        let left = RgbaImage::from_fn(10, 10, |x, _| {
+            if x < 5 {
+                Rgba([0, 0, 0, 255])
+            } else {
+                Rgba([255, 255, 255, 255])
+            }
+        });
+        let mut right = left.clone();
+        right.put_pixel(5, 4, Rgba([128, 128, 128, 255]));
+        let mut config = CompareConfig::default();
+        assert!(matches!(
+            compute_image_diff(&config, &left, &right),
+            Difference::Content(ContentDifference {
+                n_different_pixels: 1,
+                n_antialiased_pixels: 0,
+                ..
+            })
+        ));
+        config.set_detect_antialiasing(true);
+        let Difference::WithinTolerance(content) = compute_image_diff(&config, &left, &right)
+        else {
+            panic!("Anti-aliasing not tolerated");
+        };
+        assert_eq!(content.n_different_pixels, 0);
+        assert_eq!(content.n_antialiased_pixels, 1);
+        assert_eq!(
+            content.diff_image.load().unwrap().get_pixel(5, 4).0,
+            ANTIALIASING_COLOR
+        );
+
+        // An isolated changed pixel in a flat area is not anti-aliasing
+        let left = image_with_pixel(Rgba([100, 100, 100, 255]));
+        let right = image_with_pixel(Rgba([0, 0, 0, 255]));
+        assert!(matches!(
+            compute_image_diff(&config, &left, &right),
+            Difference::Content(ContentDifference {
+                n_different_pixels: 1,
+                n_antialiased_pixels: 0,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn deferred_diff_image() {
+        let dir = std::env::temp_dir().join("kompari-deferred-diff");
+        std::fs::create_dir_all(&dir).unwrap();
+        let (left, right) = (dir.join("left.png"), dir.join("right.png"));
+        let mut changed = image_with_pixel(Rgba([0, 0, 0, 255]));
+        changed.put_pixel(8, 8, Rgba([0, 0, 0, 255]));
+        image_with_pixel(Rgba([100, 100, 100, 255]))
+            .save(&left)
+            .unwrap();
+        changed.save(&right).unwrap();
+
+        let mut config = CompareConfig::default();
+        let region = Rectangle {
+            x: 8,
+            y: 8,
+            width: 1,
+            height: 1,
+        };
+        config.add_ignore_region("a.png", region);
+        let pair = Pair::new("a.png".into(), left, right.clone());
+        let mut result = compute_pair_diff(&config, pair).unwrap();
+        assert_eq!(result.ignore_mask().unwrap().regions(), [region]);
+        let in_memory = result.difference().content().unwrap().diff_image.load();
+        let in_memory = in_memory.unwrap().into_owned();
+        result.release_diff_image(&config);
+        let content = result.difference().content().unwrap();
+        assert!(matches!(content.diff_image, DiffImage::Deferred(_)));
+        let image = content.diff_image.load().unwrap();
+        assert_eq!(*image, in_memory);
+        assert_eq!(image.get_pixel(3, 4).0, [100, 0, 0, 255]);
+        assert_eq!(image.get_pixel(8, 8).0, [0, 0, 0, 255]);
+
+        image_with_pixel(Rgba([100, 100, 100, 255]))
+            .save(&right)
+            .unwrap();
+        assert!(matches!(
+            content.diff_image.load(),
+            Err(crate::Error::ImagesChanged(_))
+        ));
+    }
+
+    #[test]
+    fn results_in_order_of_pairs() {
+        let dir = std::env::temp_dir().join("kompari-results-order");
+        std::fs::create_dir_all(&dir).unwrap();
+        let left = dir.join("left.png");
+        image_with_pixel(Rgba([100, 100, 100, 255]))
+            .save(&left)
+            .unwrap();
+        for i in 0..10u8 {
+            image_with_pixel(Rgba([i * 20, 100, 100, 255]))
+                .save(dir.join(format!("right{i}.png")))
+                .unwrap();
+        }
+        // More pairs than in one chunk of the parallel version with one thread
+        let pairs = || {
+            (0..10).map(|i| {
+                let right = dir.join(format!("right{i}.png"));
+                Pair::new(format!("{i}.png"), left.clone(), right)
+            })
+        };
+        #[allow(unused_mut)]
+        let mut config = CompareConfig::default();
+        #[cfg(feature = "parallel")]
+        config.set_num_threads(Some(1));
+        let result = |pair_diff: &PairResult| {
+            let distance = pair_diff.difference.content().map(|c| c.distance_sum);
+            (pair_diff.pair.title.clone(), distance)
+        };
+        let expected: Vec<_> = pairs()
+            .map(|pair| result(&compute_pair_diff(&config, pair).unwrap()))
+            .collect();
+        let mut results = Vec::new();
+        for_each_difference(&config, pairs().collect(), |pair_diff| {
+            results.push(result(&pair_diff));
+            Ok(())
         })
-        .collect()
+        .unwrap();
+        assert_eq!(results, expected);
+    }
+
+    #[test]
+    fn diff_image_paths_keep_extensions() {
+        let dir = Path::new("diffs");
+        assert_eq!(
+            diff_image_path(dir, "sub/a.jpg"),
+            Path::new("diffs/sub/a.jpg.diff.png")
+        );
+        assert_ne!(diff_image_path(dir, "a.jpg"), diff_image_path(dir, "a.png"));
+    }
+
+    #[test]
+    fn mask_load_error_is_described() {
+        let dir = std::env::temp_dir().join("kompari-mask-load-error");
+        std::fs::create_dir_all(&dir).unwrap();
+        let (left, right) = (dir.join("left.png"), dir.join("right.png"));
+        let image = image_with_pixel(Rgba([100, 100, 100, 255]));
+        image.save(&left).unwrap();
+        image.save(&right).unwrap();
+        std::fs::write(dir.join("left.mask.png"), "not an image").unwrap();
+
+        let mut config = CompareConfig::default();
+        config.set_use_mask_files(true);
+        let result = compute_pair_diff(&config, Pair::new("a.png".into(), left, right)).unwrap();
+        let Difference::LoadError(error) = result.difference() else {
+            panic!("Unexpected result {:?}", result.difference());
+        };
+        assert!(error.contains("left.mask.png"), "{error}");
+        assert!(!result.description().ends_with("()"));
+    }
 }
@@ -0,0 +1,121 @@
+// Copyright 2024 the Kompari Authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Filtering of image names by glob and regex patterns.
+
+use globset::{GlobBuilder, GlobMatcher};
+use regex::Regex;
+
+/// Prefix of patterns that are regular expressions
+const REGEX_PREFIX: &str = "re:";
+/// Prefix of glob patterns, needed for globs without metacharacters
+const GLOB_PREFIX: &str = "glob:";
+/// Characters making a pattern without a prefix a glob
+const GLOB_METACHARACTERS: &[char] = &['*', '?', '[', ']', '{', '}'];
+
+#[derive(Debug)]
+enum Matcher {
+    Substring(String),
+    Glob(GlobMatcher),
+    Regex(Regex),
+}
+
+impl Matcher {
+    /// Patterns starting with `re:` are regular expressions searched anywhere in the name,
+    /// patterns starting with `glob:` or containing glob metacharacters are globs matching
+    /// the whole name, other patterns are searched anywhere in the name.
+    fn parse(pattern: &str) -> crate::Result<Self> {
+        let error = |e: &dyn std::fmt::Display| {
+            crate::Error::InvalidPattern(pattern.to_string(), e.to_string())
+        };
+        if let Some(regex) = pattern.strip_prefix(REGEX_PREFIX) {
+            Ok(Matcher::Regex(Regex::new(regex).map_err(|e| error(&e))?))
+        } else if !pattern.starts_with(GLOB_PREFIX) && !pattern.contains(GLOB_METACHARACTERS) {
+            Ok(Matcher::Substring(pattern.to_string()))
+        } else {
+            let glob = pattern.strip_prefix(GLOB_PREFIX).unwrap_or(pattern);
+            let glob = GlobBuilder::new(glob)
+                .literal_separator(true)
+                .build()
+                .map_err(|e| error(&e))?;
+            Ok(Matcher::Glob(glob.compile_matcher()))
+        }
+    }
+
+    fn is_match(&self, name: &str) -> bool {
+        match self {
+            Matcher::Substring(substring) => name.contains(substring.as_str()),
+            Matcher::Glob(glob) => glob.is_match(name),
+            Matcher::Regex(regex) => regex.is_match(name),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct NameFilter {
+    include: Vec<Matcher>,
+    exclude: Vec<Matcher>,
+}
+
+impl NameFilter {
+    pub fn add_include(&mut self, pattern: &str) -> crate::Result<()> {
+        self.include.push(Matcher::parse(pattern)?);
+        Ok(())
+    }
+
+    pub fn add_exclude(&mut self, pattern: &str) -> crate::Result<()> {
+        self.exclude.push(Matcher::parse(pattern)?);
+        Ok(())
+    }
+
+    /// A name is accepted when it matches some include pattern (or there are none)
+    /// and it does not match any exclude pattern
+    pub fn is_match(&self, name: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|m| m.is_match(name)))
+            && !self.exclude.iter().any(|m| m.is_match(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_name_filter() {
+        let mut filter = NameFilter::default();
+        assert!(filter.is_match("anything.png"));
+
+        filter.add_include("widgets/**/button_*.png").unwrap();
+        filter.add_include("re:^icons/.*_dark").unwrap();
+        assert!(filter.is_match("widgets/button_ok.png"));
+        assert!(filter.is_match("widgets/a/b/button_ok.png"));
+        assert!(!filter.is_match("widgets/a/label_ok.png"));
+        assert!(!filter.is_match("button_ok.png"));
+        assert!(filter.is_match("icons/sub/close_dark.png"));
+
+        filter.add_exclude("glob:widgets/a/*").unwrap();
+        assert!(filter.is_match("widgets/button_ok.png"));
+        assert!(!filter.is_match("widgets/a/button_ok.png"));
+        assert!(filter.is_match("widgets/a/b/button_ok.png"));
+
+        assert!(matches!(
+            filter.add_include("re:(unclosed"),
+            Err(crate::Error::InvalidPattern(..))
+        ));
+        assert!(filter.add_exclude("[unclosed").is_err());
+    }
+
+    #[test]
+    fn plain_patterns_match_substrings() {
+        let mut filter = NameFilter::default();
+        filter.add_include("example").unwrap();
+        assert!(filter.is_match("example1.png"));
+        assert!(filter.is_match("sub/an_example.png"));
+        assert!(!filter.is_match("other.png"));
+
+        let mut filter = NameFilter::default();
+        filter.add_include("glob:example").unwrap();
+        assert!(filter.is_match("example"));
+        assert!(!filter.is_match("example1.png"));
+    }
+}
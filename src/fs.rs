@@ -4,18 +4,47 @@
 use std::ffi::{OsStr, OsString};
 use std::path::{Path, PathBuf};
 
+/// Extensions of image files for the enabled image formats
+const IMAGE_EXTENSIONS: &[&str] = &[
+    "png",
+    #[cfg(feature = "jpeg")]
+    "jpg",
+    #[cfg(feature = "jpeg")]
+    "jpeg",
+    #[cfg(feature = "webp")]
+    "webp",
+    #[cfg(feature = "tiff")]
+    "tif",
+    #[cfg(feature = "tiff")]
+    "tiff",
+    #[cfg(feature = "bmp")]
+    "bmp",
+    #[cfg(feature = "qoi")]
+    "qoi",
+    #[cfg(feature = "gif")]
+    "gif",
+];
+
+pub(crate) fn is_image_extension(ext: &str) -> bool {
+    IMAGE_EXTENSIONS
+        .iter()
+        .any(|image_ext| image_ext.eq_ignore_ascii_case(ext))
+}
+
+fn is_image_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(OsStr::to_str)
+        .map(is_image_extension)
+        .unwrap_or(false)
+}
+
 pub(crate) fn list_image_dir(
     dir_path: &Path,
 ) -> Result<impl Iterator<Item = PathBuf>, std::io::Error> {
     Ok(std::fs::read_dir(dir_path)?.filter_map(|entry| {
         if let Ok(entry) = entry {
             let path = entry.path();
-            if path
-                .extension()
-                .and_then(OsStr::to_str)
-                .map(|ext| ext.to_ascii_lowercase() == "png")
-                .unwrap_or(false)
-            {
+            if is_image_file(&path) {
                 Some(path)
             } else {
                 None
@@ -26,8 +55,60 @@ pub(crate) fn list_image_dir(
     }))
 }
 
+/// Lists images in the directory and all its subdirectories;
+/// returned paths are relative to `dir_path`
+pub(crate) fn list_image_dir_recursive(dir_path: &Path) -> Result<Vec<PathBuf>, std::io::Error> {
+    let mut paths = Vec::new();
+    let mut dirs = vec![PathBuf::new()];
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(dir_path.join(&dir))? {
+            let entry = entry?;
+            let path = dir.join(entry.file_name());
+            if entry.file_type()?.is_dir() {
+                dirs.push(path);
+            } else if is_image_file(&path) {
+                paths.push(path);
+            }
+        }
+    }
+    Ok(paths)
+}
+
 pub(crate) fn list_image_dir_names(
     dir_path: &Path,
 ) -> Result<impl Iterator<Item = OsString>, std::io::Error> {
     Ok(list_image_dir(dir_path)?.filter_map(|p| p.file_name().map(|name| name.to_os_string())))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_image_extensions() {
+        assert!(is_image_extension("png"));
+        assert!(is_image_extension("PNG"));
+        assert!(!is_image_extension("txt"));
+        assert_eq!(is_image_extension("jpg"), cfg!(feature = "jpeg"));
+        assert_eq!(is_image_extension("webp"), cfg!(feature = "webp"));
+    }
+
+    #[test]
+    fn test_list_image_dir_recursive() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests");
+        let mut paths = list_image_dir_recursive(&dir).unwrap();
+        paths.sort();
+        assert_eq!(
+            paths,
+            [
+                "left/example1.png",
+                "left/right_missing.png",
+                "left/size_error.png",
+                "right/example1.png",
+                "right/left_missing.png",
+                "right/size_error.png",
+            ]
+            .map(PathBuf::from)
+        );
+    }
+}
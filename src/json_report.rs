@@ -0,0 +1,286 @@
+// Copyright 2024 the Kompari Authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Machine-readable report in JSON or NDJSON (one JSON object per line).
+
+use crate::difference::{
+    diff_image_path, ContentDifference, DiffImage, Difference, ImageInfoResult, PairResult,
+};
+use crate::regions::DifferenceRegion;
+use crate::{ImageDiff, Rectangle, ReportConfig, Reporter};
+use image::ImageFormat;
+use serde_json::{json, Value};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Directory where difference images of a report are written, e.g. `report-diffs`
+/// for `report.json`
+fn diff_image_dir(output: &Path) -> PathBuf {
+    let mut name = output
+        .file_stem()
+        .map(|stem| stem.to_os_string())
+        .unwrap_or_default();
+    name.push("-diffs");
+    output.with_file_name(name)
+}
+
+fn rectangle_json(rect: &Rectangle) -> Value {
+    json!({
+        "x": rect.x,
+        "y": rect.y,
+        "width": rect.width,
+        "height": rect.height,
+    })
+}
+
+fn image_info_json(info: &ImageInfoResult, path: &Path) -> Value {
+    match info {
+        ImageInfoResult::Loaded(info) => json!({
+            "path": path.to_string_lossy(),
+            "state": "loaded",
+            "size": { "width": info.size.width, "height": info.size.height },
+        }),
+        ImageInfoResult::Missing => json!({
+            "path": path.to_string_lossy(),
+            "state": "missing",
+        }),
+        ImageInfoResult::Error(err) => json!({
+            "path": path.to_string_lossy(),
+            "state": "error",
+            "error": err,
+        }),
+    }
+}
+
+fn content_json(kind: &str, content: &ContentDifference, diff_image: &Path) -> Value {
+    json!({
+        "kind": kind,
+        "n_different_pixels": content.n_different_pixels,
+        "n_antialiased_pixels": content.n_antialiased_pixels,
+        "distance_sum": content.distance_sum,
+        "ssim": content.ssim,
+        "max_delta_e": content.max_delta_e,
+        "mean_delta_e": content.mean_delta_e,
+        "bounding_box": content.bounding_box.as_ref().map(rectangle_json),
+        "regions": content.regions.iter().map(|DifferenceRegion { bounding_box, n_pixels }| json!({
+            "bounding_box": rectangle_json(bounding_box),
+            "n_pixels": n_pixels,
+        })).collect::<Vec<_>>(),
+        "diff_image": diff_image.to_string_lossy(),
+    })
+}
+
+/// Writes the difference image (if any) into `diff_dir` and returns JSON of the result
+fn pair_result_json(pair_diff: &PairResult, diff_dir: &Path) -> crate::Result<Value> {
+    let difference = match &pair_diff.difference {
+        Difference::None => json!({ "kind": "none" }),
+        Difference::MissingFile => json!({ "kind": "missing_file" }),
+        Difference::LoadError(error) => json!({ "kind": "load_error", "error": error }),
+        Difference::SizeMismatch => json!({ "kind": "size_mismatch" }),
+        Difference::WithinTolerance(content) | Difference::Content(content) => {
+            let kind = if matches!(pair_diff.difference, Difference::Content(_)) {
+                "content"
+            } else {
+                "within_tolerance"
+            };
+            let path = diff_image_path(diff_dir, &pair_diff.pair.title);
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            match &content.diff_image {
+                // The file is already in place when the directories are the same
+                DiffImage::File(file) if *file == path => {}
+                DiffImage::File(file) => {
+                    std::fs::copy(file, &path)?;
+                }
+                diff_image => diff_image
+                    .load()?
+                    .save_with_format(&path, ImageFormat::Png)?,
+            }
+            content_json(kind, content, &path)
+        }
+    };
+    Ok(json!({
+        "title": pair_diff.pair.title,
+        "left": image_info_json(&pair_diff.left_info, &pair_diff.pair.left),
+        "right": image_info_json(&pair_diff.right_info, &pair_diff.pair.right),
+        "difference": difference,
+    }))
+}
+
+/// Writes results as JSON; difference images are written into a directory
+/// next to the output, e.g. `report-diffs` for `report.json`
+pub struct JsonReporter<'a> {
+    output: &'a Path,
+    ndjson: bool,
+}
+
+impl<'a> JsonReporter<'a> {
+    /// With `ndjson`, each image pair is written as one JSON object per line
+    pub fn new(output: &'a Path, ndjson: bool) -> Self {
+        JsonReporter { output, ndjson }
+    }
+}
+
+impl Reporter for JsonReporter<'_> {
+    fn write_report(&self, image_diff: &ImageDiff, config: &ReportConfig) -> crate::Result<()> {
+        create_json_report(config, image_diff.results(), self.output, self.ndjson)
+    }
+}
+
+fn create_json_report(
+    config: &ReportConfig,
+    diffs: &[PairResult],
+    output: &Path,
+    ndjson: bool,
+) -> crate::Result<()> {
+    let diff_dir = diff_image_dir(output);
+    let mut file = BufWriter::new(File::create(output)?);
+    if ndjson {
+        for pair_diff in diffs {
+            serde_json::to_writer(&mut file, &pair_result_json(pair_diff, &diff_dir)?)
+                .map_err(std::io::Error::from)?;
+            file.write_all(b"\n")?;
+        }
+    } else {
+        let results = diffs
+            .iter()
+            .map(|pair_diff| pair_result_json(pair_diff, &diff_dir))
+            .collect::<crate::Result<Vec<_>>>()?;
+        let report = json!({
+            "generator": format!("Kompari {}", env!("CARGO_PKG_VERSION")),
+            "left_title": config.left_title,
+            "right_title": config.right_title,
+            "results": results,
+        });
+        serde_json::to_writer_pretty(&mut file, &report).map_err(std::io::Error::from)?;
+        file.write_all(b"\n")?;
+    }
+    file.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_image_dir() {
+        assert_eq!(
+            diff_image_dir(Path::new("out/report.json")),
+            Path::new("out/report-diffs")
+        );
+    }
+
+    /// Returns the directory of the report, the report and the compared results
+    fn write_test_report(name: &str, ndjson: bool) -> (PathBuf, String, ImageDiff) {
+        let dir = std::env::temp_dir().join(name);
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut image_diff = ImageDiff::default();
+        image_diff
+            .compare_directories(
+                &crate::CompareConfig::default(),
+                Path::new("tests/left"),
+                Path::new("tests/right"),
+            )
+            .unwrap();
+        let output = dir.join("report.json");
+        JsonReporter::new(&output, ndjson)
+            .write_report(&image_diff, &ReportConfig::default())
+            .unwrap();
+        let report = std::fs::read_to_string(&output).unwrap();
+        (dir, report, image_diff)
+    }
+
+    fn check_results(results: &[Value], dir: &Path, image_diff: &ImageDiff) {
+        let titles: Vec<_> = results
+            .iter()
+            .map(|r| r["title"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            titles,
+            [
+                "example1.png",
+                "left_missing.png",
+                "right_missing.png",
+                "size_error.png"
+            ]
+        );
+        let kinds: Vec<_> = results
+            .iter()
+            .map(|r| r["difference"]["kind"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            kinds,
+            ["content", "missing_file", "missing_file", "size_mismatch"]
+        );
+
+        let example = &results[0];
+        assert_eq!(
+            example["left"]["path"],
+            Path::new("tests/left")
+                .join("example1.png")
+                .to_string_lossy()
+                .as_ref()
+        );
+        assert_eq!(
+            example["right"]["path"],
+            Path::new("tests/right")
+                .join("example1.png")
+                .to_string_lossy()
+                .as_ref()
+        );
+        assert_eq!(example["left"]["state"], "loaded");
+        assert_eq!(
+            example["left"]["size"],
+            json!({ "width": 125, "height": 88 })
+        );
+        let difference = &example["difference"];
+        let content = image_diff.results()[0].difference().content().unwrap();
+        assert_eq!(difference["n_different_pixels"], content.n_different_pixels);
+        assert_eq!(
+            difference["regions"].as_array().unwrap().len(),
+            content.regions.len()
+        );
+        let diff_image = dir.join("report-diffs").join("example1.png.diff.png");
+        assert_eq!(
+            difference["diff_image"],
+            diff_image.to_string_lossy().as_ref()
+        );
+        assert!(diff_image.exists());
+
+        assert_eq!(results[1]["left"]["state"], "missing");
+        assert_eq!(results[1]["right"]["state"], "loaded");
+        assert_eq!(results[2]["right"]["state"], "missing");
+    }
+
+    #[test]
+    fn json_report() {
+        let (dir, report, image_diff) = write_test_report("kompari-json-report", false);
+        let report: Value = serde_json::from_str(&report).unwrap();
+        assert_eq!(report["left_title"], "Left image");
+        assert_eq!(report["right_title"], "Right image");
+        check_results(report["results"].as_array().unwrap(), &dir, &image_diff);
+    }
+
+    #[test]
+    fn ndjson_report() {
+        let (dir, report, image_diff) = write_test_report("kompari-ndjson-report", true);
+        let results = report
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect::<Vec<Value>>();
+        check_results(&results, &dir, &image_diff);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_paths() {
+        use std::os::unix::ffi::OsStrExt;
+        let path = Path::new(std::ffi::OsStr::from_bytes(b"a\xff.png"));
+        let json = image_info_json(&ImageInfoResult::Missing, path);
+        assert_eq!(json["path"], "a\u{fffd}.png");
+    }
+}
@@ -0,0 +1,164 @@
+// Copyright 2024 the Kompari Authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! JUnit XML report for CI test dashboards; each image pair is a test case.
+
+use crate::difference::{Difference, ImageInfoResult, PairResult};
+use crate::report::entry_anchor;
+use crate::{ImageDiff, ReportConfig, Reporter};
+use chrono::SubsecRound;
+use std::fmt::Write as _;
+use std::path::Path;
+
+const SUITE_NAME: &str = "kompari";
+
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            // Control characters are not allowed in XML 1.0
+            c if c.is_control() && c != '\n' && c != '\t' && c != '\r' => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn image_state(title: &str, info: &ImageInfoResult) -> String {
+    match info {
+        ImageInfoResult::Loaded(info) => format!("{title}: {}", info.size),
+        ImageInfoResult::Missing => format!("{title}: missing"),
+        ImageInfoResult::Error(err) => format!("{title}: {err}"),
+    }
+}
+
+/// Returns the failure type and the failure details, `None` if the test case passed
+fn failure(config: &ReportConfig, pair_diff: &PairResult) -> Option<(&'static str, String)> {
+    let images = || {
+        format!(
+            "{}\n{}",
+            image_state(config.left_title, &pair_diff.left_info),
+            image_state(config.right_title, &pair_diff.right_info)
+        )
+    };
+    match &pair_diff.difference {
+        Difference::None | Difference::WithinTolerance(_) => None,
+        Difference::MissingFile => Some(("missing_file", images())),
+        Difference::LoadError(error) => Some(("load_error", error.clone())),
+        Difference::SizeMismatch => Some(("size_mismatch", images())),
+        Difference::Content(content) => Some(("content", content.stats_summary())),
+    }
+}
+
+fn render_junit_report(config: &ReportConfig, diffs: &[PairResult]) -> String {
+    let failures: Vec<_> = diffs.iter().map(|d| failure(config, d)).collect();
+    let n_failures = failures.iter().filter(|f| f.is_some()).count();
+    let timestamp = chrono::Local::now().round_subsecs(0).to_rfc3339();
+
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let _ = writeln!(
+        xml,
+        "<testsuites name=\"{SUITE_NAME}\" tests=\"{}\" failures=\"{n_failures}\" errors=\"0\">",
+        diffs.len()
+    );
+    let _ = writeln!(
+        xml,
+        "  <testsuite name=\"{SUITE_NAME}\" tests=\"{}\" failures=\"{n_failures}\" errors=\"0\" skipped=\"0\" timestamp=\"{timestamp}\">",
+        diffs.len()
+    );
+    for (pair_diff, failure) in diffs.iter().zip(failures) {
+        let name = escape_xml(&pair_diff.pair.title);
+        let Some((failure_type, details)) = failure else {
+            let _ = writeln!(
+                xml,
+                "    <testcase name=\"{name}\" classname=\"{SUITE_NAME}\"/>"
+            );
+            continue;
+        };
+        let mut message = pair_diff.difference.status().to_string();
+        if let Some(html_report) = config.html_report_link {
+            let _ = write!(
+                message,
+                "; see {html_report}#{}",
+                entry_anchor(&pair_diff.pair.title)
+            );
+        }
+        let _ = writeln!(
+            xml,
+            "    <testcase name=\"{name}\" classname=\"{SUITE_NAME}\">"
+        );
+        let _ = writeln!(
+            xml,
+            "      <failure message=\"{}\" type=\"{failure_type}\">{}\n{}</failure>",
+            escape_xml(&message),
+            escape_xml(&message),
+            escape_xml(&details),
+        );
+        xml.push_str("    </testcase>\n");
+    }
+    xml.push_str("  </testsuite>\n</testsuites>\n");
+    xml
+}
+
+/// Writes JUnit XML, each image pair is a test case
+pub struct JunitReporter<'a> {
+    output: &'a Path,
+}
+
+impl<'a> JunitReporter<'a> {
+    pub fn new(output: &'a Path) -> Self {
+        JunitReporter { output }
+    }
+}
+
+impl Reporter for JunitReporter<'_> {
+    fn write_report(&self, image_diff: &ImageDiff, config: &ReportConfig) -> crate::Result<()> {
+        create_junit_report(config, image_diff.results(), self.output)
+    }
+}
+
+fn create_junit_report(
+    config: &ReportConfig,
+    diffs: &[PairResult],
+    output: &Path,
+) -> crate::Result<()> {
+    std::fs::write(output, render_junit_report(config, diffs))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::pair_result;
+
+    #[test]
+    fn test_escape_xml() {
+        assert_eq!(
+            escape_xml("<a & 'b'>\"\u{1}"),
+            "&lt;a &amp; &apos;b&apos;&gt;&quot;"
+        );
+    }
+
+    #[test]
+    fn test_junit_report() {
+        let mut config = ReportConfig::default();
+        config.set_html_report_link(Some("report.html"));
+        let diffs = [
+            pair_result("ok.png", Difference::None),
+            pair_result("a&b.png", Difference::MissingFile),
+        ];
+        let xml = render_junit_report(&config, &diffs);
+        assert!(xml.contains("tests=\"2\" failures=\"1\""));
+        assert!(xml.contains("<testcase name=\"ok.png\" classname=\"kompari\"/>"));
+        assert!(xml.contains(
+            "<failure message=\"Missing file; see report.html#diff-a_26b.png\" type=\"missing_file\">"
+        ));
+        assert!(xml.contains("Left image: 10x20\nRight image: missing</failure>"));
+    }
+}
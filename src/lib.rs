@@ -1,22 +1,54 @@
 // Copyright 2024 the Kompari Authors
 // SPDX-License-Identifier: Apache-2.0 OR MIT
 
-use crate::difference::{compute_differences, Difference, ImageInfoResult, PairResult};
-use image::ImageError;
+use crate::difference::{compare_rgba_images, for_each_difference};
+use image::{GenericImageView, ImageError, Pixel, Rgb, RgbaImage};
+use std::collections::{BTreeSet, HashMap};
 use std::path::{Path, PathBuf};
 
+use crate::bless::bless_pair;
+use crate::filter::NameFilter;
 use crate::pair::pairs_from_paths;
-use crate::report::create_html_report;
 use thiserror::Error;
 
+pub use crate::bless::{read_accept_list, BlessOutcome, ACCEPT_LIST_FILENAME};
+pub use crate::difference::{
+    ContentDifference, DiffImage, DiffSource, Difference, DifferenceKind, ImageInfo,
+    ImageInfoResult, PairResult, Size,
+};
+pub use crate::json_report::JsonReporter;
+pub use crate::junit_report::JunitReporter;
+pub use crate::mask::IgnoreMask;
+pub use crate::regions::DifferenceRegion;
+pub use crate::report::{HtmlReportWriter, HtmlReporter};
+
+mod bless;
+mod color;
 mod difference;
+mod filter;
 mod fs;
+mod json_report;
+mod junit_report;
+mod mask;
 mod pair;
+mod regions;
 mod report;
+#[cfg(feature = "serde")]
+mod serde_png;
+pub mod snapshot;
+mod ssim;
+#[cfg(test)]
+mod test_utils;
+#[cfg(feature = "tui")]
+mod tui;
 
+#[cfg(feature = "xtask-cli")]
+mod review_server;
 #[cfg(feature = "xtask-cli")]
 pub mod xtask_cli;
 
+// Variants do not depend on features; enabling a feature must not break
+// exhaustive matches in other crates
 #[derive(Error, Debug)]
 pub enum Error {
     #[error("IO error")]
@@ -27,19 +59,78 @@ pub enum Error {
 
     #[error("Image error")]
     ImageError(#[from] ImageError),
+
+    #[error("Invalid pattern `{0}`: {1}")]
+    InvalidPattern(String, String),
+
+    #[error("Images `{0}` changed since the comparison, the difference image cannot be created")]
+    ImagesChanged(String),
+
+    #[error("Server error: {0}")]
+    ServerError(String),
+
+    #[error("Thread pool error: {0}")]
+    ThreadPoolError(String),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// Content of the generated difference image
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DiffImageMode {
+    /// The largest channel difference of each pixel
+    #[default]
+    Pixels,
+    /// Per-pixel structural dissimilarity (1 - SSIM)
+    Ssim,
+}
+
+/// Metric deciding whether two pixels are different
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ColorDistance {
+    /// The largest difference in a single channel, compared against pixel tolerance
+    #[default]
+    Channel,
+    /// Perceptual CIEDE2000 color difference, compared against delta E tolerance
+    DeltaE2000,
+}
+
+/// Rectangular area of an image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Rectangle {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
 #[derive(Default)]
-pub struct CompareConfig<'a> {
+pub struct CompareConfig {
     ignore_match: bool,
     ignore_left_missing: bool,
     ignore_right_missing: bool,
-    filter_name: Option<&'a str>,
+    name_filter: NameFilter,
+    pixel_tolerance: u8,
+    max_different_pixels: Option<u64>,
+    max_different_pixels_percent: Option<f32>,
+    background: Option<Rgb<u8>>,
+    min_ssim: Option<f64>,
+    diff_image_mode: DiffImageMode,
+    color_distance: ColorDistance,
+    delta_e_tolerance: f32,
+    detect_antialiasing: bool,
+    ignore_regions: HashMap<String, Vec<Rectangle>>,
+    use_mask_files: bool,
+    recursive: bool,
+    diff_image_dir: Option<PathBuf>,
+    #[cfg(feature = "parallel")]
+    num_threads: Option<usize>,
 }
 
-impl<'a> CompareConfig<'a> {
+impl CompareConfig {
     pub fn set_ignore_match(&mut self, value: bool) {
         self.ignore_match = value;
     }
@@ -52,15 +143,166 @@ impl<'a> CompareConfig<'a> {
         self.ignore_right_missing = value;
     }
 
-    pub fn set_filter_name(&mut self, value: Option<&'a str>) {
-        self.filter_name = value;
+    /// Compare only images whose name matches at least one of the include patterns.
+    ///
+    /// Patterns with glob metacharacters (`*?[]{}`) or prefixed with `glob:` are globs
+    /// (e.g. `widgets/**/button_*.png`) matched against the whole name. Patterns prefixed
+    /// with `re:` are regular expressions and other patterns are plain text, both searched
+    /// anywhere in the name. When comparing recursively, the name is the relative path
+    /// with '/' separators.
+    pub fn add_include_pattern(&mut self, pattern: &str) -> Result<()> {
+        self.name_filter.add_include(pattern)
+    }
+
+    /// Skip images whose name matches the pattern; see [`CompareConfig::add_include_pattern`]
+    /// for the pattern syntax
+    pub fn add_exclude_pattern(&mut self, pattern: &str) -> Result<()> {
+        self.name_filter.add_exclude(pattern)
+    }
+
+    /// Maximal difference in a single color channel for which pixels are still considered equal
+    pub fn set_pixel_tolerance(&mut self, value: u8) {
+        self.pixel_tolerance = value;
+    }
+
+    /// Maximal number of different pixels for which images are still considered within tolerance
+    pub fn set_max_different_pixels(&mut self, value: Option<u64>) {
+        self.max_different_pixels = value;
+    }
+
+    /// Maximal percentage (0-100) of different pixels for which images are still considered
+    /// within tolerance
+    pub fn set_max_different_pixels_percent(&mut self, value: Option<f32>) {
+        self.max_different_pixels_percent = value;
+    }
+
+    /// When set, images are composited over the given background color before comparison,
+    /// otherwise the alpha channel is compared as any other channel
+    pub fn set_background(&mut self, value: Option<Rgb<u8>>) {
+        self.background = value;
+    }
+
+    /// Minimal structural similarity (SSIM, 0.0-1.0) for which images are still considered
+    /// within tolerance; the similarity of a pixel is the minimum over color channels
+    pub fn set_min_ssim(&mut self, value: Option<f64>) {
+        self.min_ssim = value;
+    }
+
+    pub fn set_diff_image_mode(&mut self, value: DiffImageMode) {
+        self.diff_image_mode = value;
+    }
+
+    /// Metric used for detecting different pixels and for the intensity of the difference image
+    pub fn set_color_distance(&mut self, value: ColorDistance) {
+        self.color_distance = value;
+    }
+
+    /// Maximal CIEDE2000 difference for which pixels are still considered equal,
+    /// used with [`ColorDistance::DeltaE2000`]
+    pub fn set_delta_e_tolerance(&mut self, value: f32) {
+        self.delta_e_tolerance = value;
+    }
+
+    /// Detect different pixels caused by anti-aliasing and exclude them from the number of
+    /// different pixels
+    pub fn set_detect_antialiasing(&mut self, value: bool) {
+        self.detect_antialiasing = value;
+    }
+
+    /// Ignore a region of the image with the given name during comparison;
+    /// the name is the relative path with '/' separators when comparing recursively
+    pub fn add_ignore_region(&mut self, image_name: &str, region: Rectangle) {
+        self.ignore_regions
+            .entry(image_name.to_string())
+            .or_default()
+            .push(region);
+    }
+
+    /// Use sidecar mask files (`foo.mask.png` for `foo.png`) next to the left or right image.
+    /// Pixels of the mask that are neither black nor fully transparent are ignored.
+    /// Mask files are then not compared as images; otherwise they are ordinary images.
+    pub fn set_use_mask_files(&mut self, value: bool) {
+        self.use_mask_files = value;
+    }
+
+    /// Compare images in subdirectories as well; images are paired by their relative paths
+    pub fn set_recursive(&mut self, value: bool) {
+        self.recursive = value;
+    }
+
+    /// Write difference images as PNG files into the directory during the comparison.
+    /// By default, difference images of compared files are not kept and they are created
+    /// again from the image files when needed (e.g. by reports).
+    pub fn set_diff_image_dir(&mut self, dir: Option<PathBuf>) {
+        self.diff_image_dir = dir;
+    }
+
+    /// Number of threads comparing image pairs, all available cores are used by default
+    #[cfg(feature = "parallel")]
+    pub fn set_num_threads(&mut self, value: Option<usize>) {
+        self.num_threads = value;
     }
+
+    /// All configured criteria have to be satisfied. When only the minimal SSIM is configured,
+    /// the number of different pixels is not taken into account.
+    pub(crate) fn is_within_tolerance(&self, content: &ContentDifference, n_pixels: u64) -> bool {
+        let n_different_pixels = content.n_different_pixels;
+        let pixels_ok = if self.max_different_pixels.is_none()
+            && self.max_different_pixels_percent.is_none()
+        {
+            n_different_pixels == 0 || self.min_ssim.is_some()
+        } else {
+            let count_ok = self
+                .max_different_pixels
+                .map(|limit| n_different_pixels <= limit)
+                .unwrap_or(true);
+            let percent_ok = self
+                .max_different_pixels_percent
+                .map(|limit| (n_different_pixels as f64 / n_pixels as f64 * 100.0) <= limit as f64)
+                .unwrap_or(true);
+            count_ok && percent_ok
+        };
+        let ssim_ok = self
+            .min_ssim
+            .map(|limit| content.ssim.is_some_and(|ssim| ssim >= limit))
+            .unwrap_or(true);
+        pixels_ok && ssim_ok
+    }
+}
+
+/// Output format of a report
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    #[default]
+    Html,
+    /// A single JSON document; difference images are written next to the report
+    Json,
+    /// One JSON object per line for each image pair
+    Ndjson,
+    /// JUnit XML, each image pair is a test case
+    Junit,
+}
+
+/// Graphics used for image previews in the terminal review
+#[cfg(feature = "tui")]
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TerminalGraphics {
+    /// The kitty graphics protocol when the terminal is known to support it, half blocks otherwise
+    #[default]
+    Auto,
+    Kitty,
+    /// Colored "upper half block" characters, two pixels per character
+    HalfBlocks,
 }
 
 pub struct ReportConfig<'a> {
     left_title: &'a str,
     right_title: &'a str,
     embed_images: bool,
+    format: ReportFormat,
+    html_report_link: Option<&'a str>,
+    /// Token of the review server; entries have accept/reject buttons calling the server
+    review_token: Option<&'a str>,
 }
 
 impl Default for ReportConfig<'_> {
@@ -69,6 +311,9 @@ impl Default for ReportConfig<'_> {
             left_title: "Left image",
             right_title: "Right image",
             embed_images: false,
+            format: ReportFormat::Html,
+            html_report_link: None,
+            review_token: None,
         }
     }
 }
@@ -85,11 +330,274 @@ impl<'a> ReportConfig<'a> {
     pub fn set_embed_images(&mut self, embed_images: bool) {
         self.embed_images = embed_images;
     }
+
+    pub fn set_format(&mut self, format: ReportFormat) {
+        self.format = format;
+    }
+
+    /// Path or URL of an HTML report of the same comparison;
+    /// non-HTML reports link their entries to it
+    pub fn set_html_report_link(&mut self, link: Option<&'a str>) {
+        self.html_report_link = link;
+    }
+
+    pub fn left_title(&self) -> &'a str {
+        self.left_title
+    }
+
+    pub fn right_title(&self) -> &'a str {
+        self.right_title
+    }
+
+    pub fn embed_images(&self) -> bool {
+        self.embed_images
+    }
+
+    pub fn format(&self) -> ReportFormat {
+        self.format
+    }
+
+    pub fn html_report_link(&self) -> Option<&'a str> {
+        self.html_report_link
+    }
+}
+
+/// Output of compared results, e.g. an HTML or JSON file.
+///
+/// Kompari provides [`HtmlReporter`], [`JsonReporter`] and [`JunitReporter`],
+/// other outputs (e.g. Markdown summaries) can be added by implementing this trait.
+pub trait Reporter {
+    fn write_report(&self, image_diff: &ImageDiff, config: &ReportConfig) -> Result<()>;
+}
+
+/// Number of compared image pairs by their result
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Summary {
+    pub n_match: usize,
+    pub n_within_tolerance: usize,
+    pub n_content: usize,
+    pub n_size_mismatch: usize,
+    /// Pairs where the left image is missing
+    pub n_missing_left: usize,
+    /// Pairs where the right image is missing
+    pub n_missing_right: usize,
+    pub n_load_errors: usize,
+}
+
+impl Summary {
+    pub(crate) fn from_results(diffs: &[PairResult]) -> Self {
+        let mut summary = Summary::default();
+        for pair_diff in diffs {
+            summary.add(pair_diff);
+        }
+        summary
+    }
+
+    pub(crate) fn add(&mut self, pair_diff: &PairResult) {
+        match &pair_diff.difference {
+            Difference::None => self.n_match += 1,
+            Difference::WithinTolerance(_) => self.n_within_tolerance += 1,
+            Difference::Content(_) => self.n_content += 1,
+            Difference::SizeMismatch => self.n_size_mismatch += 1,
+            Difference::LoadError(_) => self.n_load_errors += 1,
+            Difference::MissingFile => {
+                if matches!(pair_diff.left_info, ImageInfoResult::Missing) {
+                    self.n_missing_left += 1;
+                } else {
+                    self.n_missing_right += 1;
+                }
+            }
+        }
+    }
+
+    pub fn n_total(&self) -> usize {
+        self.n_match
+            + self.n_within_tolerance
+            + self.n_content
+            + self.n_size_mismatch
+            + self.n_missing()
+            + self.n_load_errors
+    }
+
+    /// Pairs where at least one image is missing
+    pub fn n_missing(&self) -> usize {
+        self.n_missing_left + self.n_missing_right
+    }
+
+    /// Pairs with different images (different content or size)
+    pub fn n_different(&self) -> usize {
+        self.n_content + self.n_size_mismatch
+    }
+
+    /// Overall result, the most severe problem found
+    pub fn check_result(&self) -> CheckResult {
+        if self.n_load_errors > 0 {
+            CheckResult::Error
+        } else if self.n_different() > 0 {
+            CheckResult::Differences
+        } else if self.n_missing() > 0 {
+            CheckResult::Missing
+        } else if self.n_total() == 0 {
+            CheckResult::NothingCompared
+        } else {
+            CheckResult::Match
+        }
+    }
+}
+
+/// Overall result of a check, see [`Summary::check_result`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckResult {
+    /// All images match or are within tolerance
+    Match,
+    /// Some images have different content or size
+    Differences,
+    /// Some images are missing
+    Missing,
+    /// Some images cannot be loaded
+    Error,
+    /// No image pairs were compared, e.g. because of a filter matching no names
+    NothingCompared,
+}
+
+impl CheckResult {
+    /// Exit code of the `check` command; 2 is used by clap for invalid arguments
+    pub fn exit_code(self) -> i32 {
+        match self {
+            CheckResult::Match => 0,
+            CheckResult::Differences => 1,
+            CheckResult::Missing => 3,
+            CheckResult::Error => 4,
+            CheckResult::NothingCompared => 5,
+        }
+    }
+}
+
+impl std::fmt::Display for Summary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} image pairs: {} match, {} within tolerance, {} different content, \
+             {} size mismatch, {} missing left, {} missing right, {} load errors",
+            self.n_total(),
+            self.n_match,
+            self.n_within_tolerance,
+            self.n_content,
+            self.n_size_mismatch,
+            self.n_missing_left,
+            self.n_missing_right,
+            self.n_load_errors,
+        )
+    }
+}
+
+/// Result of comparing two images in memory, see [`compare_images`]
+#[derive(Debug)]
+pub enum ImageComparison {
+    /// Images are identical
+    Match,
+    /// Images have different sizes, given as (width, height)
+    SizeMismatch {
+        left: (u32, u32),
+        right: (u32, u32),
+    },
+    /// Images differ, but the difference is within the configured tolerance
+    WithinTolerance(ContentDifference),
+    Different(ContentDifference),
+}
+
+impl ImageComparison {
+    /// Statistics and the difference image, if images have the same size and differ
+    pub fn content(&self) -> Option<&ContentDifference> {
+        match self {
+            ImageComparison::WithinTolerance(content) | ImageComparison::Different(content) => {
+                Some(content)
+            }
+            ImageComparison::Match | ImageComparison::SizeMismatch { .. } => None,
+        }
+    }
+
+    /// Images neither match nor are within tolerance
+    pub fn is_failure(&self) -> bool {
+        matches!(
+            self,
+            ImageComparison::SizeMismatch { .. } | ImageComparison::Different(_)
+        )
+    }
+}
+
+fn to_rgba8<I>(image: &I) -> RgbaImage
+where
+    I: GenericImageView,
+    I::Pixel: Pixel<Subpixel = u8>,
+{
+    RgbaImage::from_fn(image.width(), image.height(), |x, y| {
+        image.get_pixel(x, y).to_rgba()
+    })
+}
+
+/// Compares two images in memory (e.g. `RgbImage`, `RgbaImage` or `DynamicImage`)
+/// without touching the filesystem.
+///
+/// All tolerances and metrics of `config` are used; ignore regions and mask files are not,
+/// as they are bound to image names.
+pub fn compare_images<L, R>(config: &CompareConfig, left: &L, right: &R) -> ImageComparison
+where
+    L: GenericImageView,
+    L::Pixel: Pixel<Subpixel = u8>,
+    R: GenericImageView,
+    R::Pixel: Pixel<Subpixel = u8>,
+{
+    let (left, right) = (to_rgba8(left), to_rgba8(right));
+    let (left_size, right_size) = (left.dimensions(), right.dimensions());
+    match compare_rgba_images(config, left, right) {
+        Difference::None => ImageComparison::Match,
+        Difference::WithinTolerance(content) => ImageComparison::WithinTolerance(content),
+        Difference::Content(content) => ImageComparison::Different(content),
+        Difference::SizeMismatch => ImageComparison::SizeMismatch {
+            left: left_size,
+            right: right_size,
+        },
+        Difference::MissingFile | Difference::LoadError(_) => {
+            unreachable!("Images in memory are neither missing nor fail to load")
+        }
+    }
+}
+
+/// Compares images in two directories and passes each result to `f` as soon as it
+/// is computed, in the order of titles.
+///
+/// Results are not collected as by [`ImageDiff::compare_directories`], `f` may write them
+/// with [`HtmlReportWriter`] during the comparison. Difference images are passed in memory (or as files with
+/// [`CompareConfig::set_diff_image_dir`]), so they are not created again when written.
+pub fn compare_directories_with(
+    config: &CompareConfig,
+    left_path: &Path,
+    right_path: &Path,
+    mut f: impl FnMut(PairResult) -> Result<()>,
+) -> Result<()> {
+    let pairs = pairs_from_paths(left_path, right_path, config)?;
+    for_each_difference(config, pairs, |pair_diff| {
+        let ignored = (config.ignore_match && matches!(pair_diff.difference, Difference::None))
+            || (config.ignore_left_missing
+                && matches!(pair_diff.left_info, ImageInfoResult::Missing))
+            || (config.ignore_right_missing
+                && matches!(pair_diff.right_info, ImageInfoResult::Missing));
+        if ignored {
+            Ok(())
+        } else {
+            f(pair_diff)
+        }
+    })
 }
 
 #[derive(Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ImageDiff {
     diffs: Vec<PairResult>,
+    /// Pairs of compared (left, right) directories
+    directories: Vec<(PathBuf, PathBuf)>,
 }
 
 impl ImageDiff {
@@ -99,22 +607,96 @@ impl ImageDiff {
         left_path: &Path,
         right_path: &Path,
     ) -> Result<()> {
-        let pairs = pairs_from_paths(left_path, right_path, config.filter_name)?;
-        let mut diffs = compute_differences(pairs);
+        compare_directories_with(config, left_path, right_path, |mut pair_diff| {
+            pair_diff.release_diff_image(config);
+            self.diffs.push(pair_diff);
+            Ok(())
+        })?;
+        self.directories
+            .push((left_path.to_path_buf(), right_path.to_path_buf()));
+        Ok(())
+    }
 
-        if config.ignore_match {
-            diffs.retain(|pair| !matches!(pair.difference, Difference::None));
-        }
+    /// Results of all compared pairs
+    pub fn results(&self) -> &[PairResult] {
+        &self.diffs
+    }
+
+    /// Pairs of compared (left, right) directories
+    pub fn directories(&self) -> &[(PathBuf, PathBuf)] {
+        &self.directories
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, PairResult> {
+        self.diffs.iter()
+    }
+
+    /// Results with the given kind of difference
+    pub fn results_of_kind(&self, kind: DifferenceKind) -> impl Iterator<Item = &PairResult> {
+        self.diffs
+            .iter()
+            .filter(move |pair_diff| pair_diff.difference.kind() == kind)
+    }
+
+    /// Number of results with the given kind of difference
+    pub fn count(&self, kind: DifferenceKind) -> usize {
+        self.results_of_kind(kind).count()
+    }
+
+    pub fn len(&self) -> usize {
+        self.diffs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.diffs.is_empty()
+    }
+
+    pub fn summary(&self) -> Summary {
+        Summary::from_results(&self.diffs)
+    }
+
+    /// Human readable descriptions of image pairs that neither match
+    /// nor are within tolerance
+    pub fn failures(&self) -> impl Iterator<Item = String> + '_ {
+        self.diffs
+            .iter()
+            .filter(|pair_diff| pair_diff.difference.is_failure())
+            .map(|pair_diff| pair_diff.description())
+    }
 
-        if config.ignore_left_missing {
-            diffs.retain(|pair| !matches!(pair.left_info, ImageInfoResult::Missing));
+    /// Copies left images over right images (e.g. current test results over snapshots)
+    /// for pairs that differ and whose title is accepted by `accept`.
+    /// Returns titles of the copied images.
+    pub fn bless(&self, accept: impl Fn(&str) -> bool) -> Result<Vec<String>> {
+        let mut blessed = Vec::new();
+        for pair_diff in &self.diffs {
+            if pair_diff.is_blessable() && accept(&pair_diff.pair.title) {
+                bless_pair(pair_diff)?;
+                blessed.push(pair_diff.pair.title.clone());
+            }
         }
+        Ok(blessed)
+    }
+
+    /// Blesses pairs whose titles are in the accept list file (see [`read_accept_list`])
+    pub fn bless_accept_list(&self, accept_list: &Path) -> Result<BlessOutcome> {
+        let accepted: BTreeSet<_> = read_accept_list(accept_list)?.into_iter().collect();
+        let blessed = self.bless(|title| accepted.contains(title))?;
+        let skipped = accepted
+            .into_iter()
+            .filter(|title| !blessed.contains(title))
+            .collect();
+        Ok(BlessOutcome { blessed, skipped })
+    }
 
-        if config.ignore_right_missing {
-            diffs.retain(|pair| !matches!(pair.right_info, ImageInfoResult::Missing));
+    /// Interactive review of the results in the terminal; accepted pairs are
+    /// blessed as by [`ImageDiff::bless`]. Returns titles of the accepted images.
+    #[cfg(feature = "tui")]
+    pub fn review(&self, config: &ReportConfig, graphics: TerminalGraphics) -> Result<Vec<String>> {
+        if self.diffs.is_empty() {
+            return Ok(Vec::new());
         }
-        self.diffs.append(&mut diffs);
-        Ok(())
+        tui::review(config, &self.diffs, graphics)
     }
 
     pub fn create_report(&self, config: &ReportConfig, output: &Path, verbose: bool) -> Result<()> {
@@ -123,7 +705,12 @@ impl ImageDiff {
             return Ok(());
         }
         let count = self.diffs.len();
-        create_html_report(config, &self.diffs, output)?;
+        match config.format {
+            ReportFormat::Html => HtmlReporter::new(output).write_report(self, config)?,
+            ReportFormat::Json => JsonReporter::new(output, false).write_report(self, config)?,
+            ReportFormat::Ndjson => JsonReporter::new(output, true).write_report(self, config)?,
+            ReportFormat::Junit => JunitReporter::new(output).write_report(self, config)?,
+        }
         if verbose {
             println!(
                 "Report written into '{}'; found {} images",
@@ -135,10 +722,279 @@ impl ImageDiff {
     }
 }
 
+impl<'a> IntoIterator for &'a ImageDiff {
+    type Item = &'a PairResult;
+    type IntoIter = std::slice::Iter<'a, PairResult>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.diffs.iter()
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    // CI will fail unless cargo nextest can execute at least one test per workspace.
-    // Delete this dummy test once we have an actual real test.
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    #[test]
+    fn compare_images_in_memory() {
+        let config = CompareConfig::default();
+        let left = RgbImage::from_pixel(10, 10, Rgb([255, 255, 255]));
+        let mut right = left.clone();
+        assert!(matches!(
+            compare_images(&config, &left, &right),
+            ImageComparison::Match
+        ));
+
+        right.put_pixel(3, 4, Rgb([0, 0, 0]));
+        let result = compare_images(&config, &left, &right);
+        assert!(result.is_failure());
+        let content = result.content().unwrap();
+        assert_eq!(content.n_different_pixels, 1);
+        assert_eq!(content.size, Size::new(10, 10));
+        assert_eq!(content.diff_image.load().unwrap().dimensions(), (10, 10));
+
+        let smaller = RgbImage::new(5, 10);
+        assert!(matches!(
+            compare_images(&config, &left, &smaller),
+            ImageComparison::SizeMismatch {
+                left: (10, 10),
+                right: (5, 10)
+            }
+        ));
+    }
+
+    struct TitleReporter(std::cell::RefCell<Vec<String>>);
+
+    impl Reporter for TitleReporter {
+        fn write_report(&self, image_diff: &ImageDiff, config: &ReportConfig) -> Result<()> {
+            let summary = image_diff.summary();
+            self.0.borrow_mut().extend(
+                image_diff
+                    .results()
+                    .iter()
+                    .map(|result| format!("{}: {}", result.title(), result.status())),
+            );
+            self.0.borrow_mut().push(format!(
+                "{} vs {}: {}",
+                config.left_title(),
+                config.right_title(),
+                summary.n_total()
+            ));
+            Ok(())
+        }
+    }
+
     #[test]
-    fn dummy_test_until_we_have_a_real_test() {}
+    fn custom_reporter() {
+        let image_diff = compare_test_dirs();
+        let reporter = TitleReporter(Default::default());
+        reporter
+            .write_report(&image_diff, &ReportConfig::default())
+            .unwrap();
+        assert_eq!(
+            reporter.0.into_inner(),
+            vec![
+                "example1.png: Different content",
+                "left_missing.png: Missing file",
+                "right_missing.png: Missing file",
+                "size_error.png: Size mismatch",
+                "Left image vs Right image: 4",
+            ]
+        );
+    }
+
+    #[test]
+    fn html_report_written_entry_by_entry() {
+        let config = ReportConfig::default();
+        let mut writer = HtmlReportWriter::new(&config, Vec::new()).unwrap();
+        let mut titles = Vec::new();
+        compare_directories_with(
+            &CompareConfig::default(),
+            Path::new("tests/left"),
+            Path::new("tests/right"),
+            |pair_diff| {
+                titles.push(pair_diff.title().to_string());
+                writer.write_entry(&pair_diff)
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            titles,
+            compare_test_dirs()
+                .iter()
+                .map(|pair_diff| pair_diff.title())
+                .collect::<Vec<_>>()
+        );
+        let report = String::from_utf8(writer.finish(&[]).unwrap()).unwrap();
+        assert_eq!(report.matches("class=\"diff-entry\"").count(), 4);
+        let summary = report.find("id=\"summary\"").unwrap();
+        assert!(report.rfind("class=\"diff-entry\"").unwrap() < summary);
+        assert!(report.ends_with("</body></html>"));
+    }
+
+    #[test]
+    fn check_results() {
+        let summary = |n_match, n_content, n_missing_left, n_load_errors| Summary {
+            n_match,
+            n_content,
+            n_missing_left,
+            n_load_errors,
+            ..Summary::default()
+        };
+        let result = |summary: Summary| summary.check_result();
+        assert_eq!(result(summary(1, 0, 0, 0)), CheckResult::Match);
+        assert_eq!(result(summary(1, 1, 1, 0)), CheckResult::Differences);
+        assert_eq!(result(summary(1, 0, 1, 0)), CheckResult::Missing);
+        assert_eq!(result(summary(1, 1, 1, 1)), CheckResult::Error);
+        assert_eq!(result(summary(0, 0, 0, 0)), CheckResult::NothingCompared);
+        assert_eq!(
+            result(Summary {
+                n_within_tolerance: 1,
+                ..Summary::default()
+            }),
+            CheckResult::Match
+        );
+        let codes = [
+            CheckResult::Match,
+            CheckResult::Differences,
+            CheckResult::Missing,
+            CheckResult::Error,
+            CheckResult::NothingCompared,
+        ]
+        .map(CheckResult::exit_code);
+        assert_eq!(codes, [0, 1, 3, 4, 5]);
+    }
+
+    #[test]
+    fn bless_accept_list() {
+        let dir = std::env::temp_dir().join("kompari-bless-accept-list");
+        let _ = std::fs::remove_dir_all(&dir);
+        for side in ["left", "right"] {
+            std::fs::create_dir_all(dir.join(side)).unwrap();
+            for entry in std::fs::read_dir(Path::new("tests").join(side)).unwrap() {
+                let path = entry.unwrap().path();
+                std::fs::copy(&path, dir.join(side).join(path.file_name().unwrap())).unwrap();
+            }
+        }
+        let accept_list = dir.join(ACCEPT_LIST_FILENAME);
+        std::fs::write(
+            &accept_list,
+            "example1.png\nleft_missing.png\nunknown.png\n",
+        )
+        .unwrap();
+
+        let mut image_diff = ImageDiff::default();
+        image_diff
+            .compare_directories(
+                &CompareConfig::default(),
+                &dir.join("left"),
+                &dir.join("right"),
+            )
+            .unwrap();
+        let outcome = image_diff.bless_accept_list(&accept_list).unwrap();
+        assert_eq!(outcome.blessed, ["example1.png"]);
+        assert_eq!(outcome.skipped, ["left_missing.png", "unknown.png"]);
+        assert_eq!(
+            std::fs::read(dir.join("left/example1.png")).unwrap(),
+            std::fs::read(dir.join("right/example1.png")).unwrap()
+        );
+    }
+
+    fn compare_test_dirs() -> ImageDiff {
+        let mut image_diff = ImageDiff::default();
+        image_diff
+            .compare_directories(
+                &CompareConfig::default(),
+                Path::new("tests/left"),
+                Path::new("tests/right"),
+            )
+            .unwrap();
+        image_diff
+    }
+
+    #[test]
+    fn inspect_results() {
+        let image_diff = compare_test_dirs();
+        assert_eq!(image_diff.len(), 4);
+        assert_eq!(image_diff.count(DifferenceKind::MissingFile), 2);
+        let content: Vec<_> = image_diff
+            .results_of_kind(DifferenceKind::Content)
+            .collect();
+        assert_eq!(content.len(), 1);
+        assert_eq!(content[0].title(), "example1.png");
+        assert!(
+            content[0]
+                .difference()
+                .content()
+                .unwrap()
+                .n_different_pixels
+                > 0
+        );
+        assert!(matches!(
+            content[0].left_info(),
+            ImageInfoResult::Loaded(ImageInfo { .. })
+        ));
+        assert_eq!((&image_diff).into_iter().count(), 4);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serialize_results() {
+        let image_diff = compare_test_dirs();
+        let json = serde_json::to_string(&image_diff).unwrap();
+        let reloaded: ImageDiff = serde_json::from_str(&json).unwrap();
+        assert_eq!(reloaded.summary(), image_diff.summary());
+        assert_eq!(reloaded.directories(), image_diff.directories());
+        let content = |diff: &ImageDiff| {
+            diff.results_of_kind(DifferenceKind::Content)
+                .next()
+                .unwrap()
+                .difference()
+                .content()
+                .unwrap()
+                .diff_image
+                .load()
+                .unwrap()
+                .into_owned()
+        };
+        assert_eq!(content(&reloaded), content(&image_diff));
+    }
+
+    #[test]
+    fn diff_images_written_to_dir() {
+        let dir = std::env::temp_dir().join("kompari-diff-image-dir");
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut config = CompareConfig::default();
+        config.set_diff_image_dir(Some(dir.clone()));
+        let mut image_diff = ImageDiff::default();
+        image_diff
+            .compare_directories(&config, Path::new("tests/left"), Path::new("tests/right"))
+            .unwrap();
+        let in_memory = compare_test_dirs();
+        let diff_image = |diff: &ImageDiff| {
+            let result = diff
+                .results_of_kind(DifferenceKind::Content)
+                .next()
+                .unwrap();
+            let content = result.difference().content().unwrap();
+            (
+                matches!(content.diff_image, DiffImage::File(_)),
+                content.diff_image.load().unwrap().into_owned(),
+            )
+        };
+        let (on_disk, image) = diff_image(&image_diff);
+        assert!(on_disk);
+        assert!(dir.join("example1.png.diff.png").exists());
+        // Without the directory, the image is created again from the files
+        assert_eq!((false, image), diff_image(&in_memory));
+        assert!(matches!(
+            in_memory.results()[0]
+                .difference()
+                .content()
+                .unwrap()
+                .diff_image,
+            DiffImage::Deferred(_)
+        ));
+    }
 }
@@ -0,0 +1,185 @@
+// Copyright 2024 the Kompari Authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Masks of image areas that are ignored during comparison.
+
+use crate::pair::Pair;
+use crate::{CompareConfig, Rectangle, Size};
+use image::{GrayImage, Luma, Rgba, RgbaImage};
+use std::path::{Path, PathBuf};
+
+/// Value of an ignored pixel in the mask
+const IGNORED: Luma<u8> = Luma([255]);
+
+/// Suffix of sidecar mask files, `foo.png` has mask `foo.mask.png`
+pub(crate) const MASK_FILE_SUFFIX: &str = ".mask.png";
+
+pub(crate) fn is_mask_file(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .map(|name| {
+            name.len() > MASK_FILE_SUFFIX.len()
+                && name[name.len() - MASK_FILE_SUFFIX.len()..]
+                    .eq_ignore_ascii_case(MASK_FILE_SUFFIX)
+        })
+        .unwrap_or(false)
+}
+
+fn mask_file_path(image_path: &Path) -> PathBuf {
+    image_path.with_extension(&MASK_FILE_SUFFIX[1..])
+}
+
+fn fill_rectangle(mask: &mut GrayImage, rect: &Rectangle) {
+    let x_end = rect.x.saturating_add(rect.width).min(mask.width());
+    let y_end = rect.y.saturating_add(rect.height).min(mask.height());
+    for y in rect.y..y_end {
+        for x in rect.x..x_end {
+            mask.put_pixel(x, y, IGNORED);
+        }
+    }
+}
+
+/// Pixels of a mask file that are neither black nor fully transparent are ignored
+fn fill_from_mask_file(mask: &mut GrayImage, path: &Path) -> crate::Result<()> {
+    let mask_image = image::ImageReader::open(path)?.decode()?.into_rgba8();
+    let width = mask.width().min(mask_image.width());
+    let height = mask.height().min(mask_image.height());
+    for y in 0..height {
+        for x in 0..width {
+            let [r, g, b, a] = mask_image.get_pixel(x, y).0;
+            if a > 0 && (r > 0 || g > 0 || b > 0) {
+                mask.put_pixel(x, y, IGNORED);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Areas of a pair of images ignored during the comparison.
+///
+/// Only the regions and paths of mask files are kept, the mask itself
+/// is rendered when needed.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IgnoreMask {
+    size: Size,
+    regions: Vec<Rectangle>,
+    mask_files: Vec<PathBuf>,
+}
+
+impl IgnoreMask {
+    /// Configured regions ignored in the image
+    pub fn regions(&self) -> &[Rectangle] {
+        &self.regions
+    }
+
+    /// Mask files next to the left or right image
+    pub fn mask_files(&self) -> &[PathBuf] {
+        &self.mask_files
+    }
+
+    /// Mask of ignored pixels (non-zero values); mask files are loaded again
+    pub fn image(&self) -> crate::Result<GrayImage> {
+        let mut mask = GrayImage::new(self.size.width, self.size.height);
+        for rect in &self.regions {
+            fill_rectangle(&mut mask, rect);
+        }
+        for path in &self.mask_files {
+            fill_from_mask_file(&mut mask, path)?;
+        }
+        Ok(mask)
+    }
+}
+
+/// Creates the ignore mask for a pair of images from the configured regions
+/// and the mask files next to the left and right image.
+/// Returns `None` when nothing is ignored.
+pub(crate) fn create_ignore_mask(
+    config: &CompareConfig,
+    pair: &Pair,
+    width: u32,
+    height: u32,
+) -> Option<IgnoreMask> {
+    let regions = config.ignore_regions.get(&pair.title);
+    let mask_files: Vec<PathBuf> = if config.use_mask_files {
+        [&pair.left, &pair.right]
+            .into_iter()
+            .map(|path| mask_file_path(path))
+            .filter(|path| path.is_file())
+            .collect()
+    } else {
+        Vec::new()
+    };
+    if regions.is_none() && mask_files.is_empty() {
+        return None;
+    }
+    Some(IgnoreMask {
+        size: Size::new(width, height),
+        regions: regions.cloned().unwrap_or_default(),
+        mask_files,
+    })
+}
+
+/// Copies masked pixels from `source` into `target`, so ignored areas are always equal
+pub(crate) fn apply_ignore_mask(mask: &GrayImage, source: &RgbaImage, target: &mut RgbaImage) {
+    for ((m, s), t) in mask.pixels().zip(source.pixels()).zip(target.pixels_mut()) {
+        if *m == IGNORED {
+            *t = *s;
+        }
+    }
+}
+
+/// Renders the ignored area as a semi-transparent hatched overlay
+pub(crate) fn mask_overlay_image(mask: &GrayImage) -> RgbaImage {
+    RgbaImage::from_fn(mask.width(), mask.height(), |x, y| {
+        if *mask.get_pixel(x, y) != IGNORED {
+            Rgba([0, 0, 0, 0])
+        } else if (x + y) % 8 < 3 {
+            Rgba([100, 100, 100, 200])
+        } else {
+            Rgba([200, 200, 200, 90])
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_mask_file() {
+        assert!(is_mask_file(Path::new("dir/foo.mask.png")));
+        assert!(is_mask_file(Path::new("foo.MASK.PNG")));
+        assert!(!is_mask_file(Path::new("foo.png")));
+        assert!(!is_mask_file(Path::new(".mask.png")));
+        assert_eq!(
+            mask_file_path(Path::new("dir/foo.png")),
+            Path::new("dir/foo.mask.png")
+        );
+    }
+
+    #[test]
+    fn test_ignore_regions() {
+        let mut config = CompareConfig::default();
+        config.add_ignore_region(
+            "foo.png",
+            Rectangle {
+                x: 2,
+                y: 3,
+                width: 20,
+                height: 1,
+            },
+        );
+        let pair = Pair::new("foo.png".into(), "a/foo.png".into(), "b/foo.png".into());
+        let mask = create_ignore_mask(&config, &pair, 10, 10)
+            .unwrap()
+            .image()
+            .unwrap();
+        assert_eq!(mask.pixels().filter(|p| **p == IGNORED).count(), 8);
+        assert_eq!(*mask.get_pixel(2, 3), IGNORED);
+        assert_ne!(*mask.get_pixel(1, 3), IGNORED);
+
+        let other = Pair::new("bar.png".into(), "a/bar.png".into(), "b/bar.png".into());
+        assert!(create_ignore_mask(&config, &other, 10, 10).is_none());
+    }
+}
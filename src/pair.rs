@@ -1,10 +1,13 @@
 // Copyright 2024 the Kompari Authors
 // SPDX-License-Identifier: Apache-2.0 OR MIT
 
-use crate::fs::list_image_dir_names;
+use crate::fs::{list_image_dir_names, list_image_dir_recursive};
+use crate::mask::is_mask_file;
+use crate::CompareConfig;
 use std::path::{Path, PathBuf};
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub(crate) struct Pair {
     pub title: String,
     pub left: PathBuf,
@@ -15,12 +18,27 @@ impl Pair {
     pub fn new(title: String, left: PathBuf, right: PathBuf) -> Self {
         Pair { title, left, right }
     }
+
+    /// Directory part of the title when pairs are created recursively
+    pub fn directory(&self) -> Option<&str> {
+        self.title.rsplit_once('/').map(|(dir, _)| dir)
+    }
+}
+
+/// Title of a pair from a relative path, components are always separated by '/'
+fn path_title(path: &Path) -> String {
+    path.iter()
+        .map(|c| c.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
 }
 
+/// Pairs of images with the same name selected by the configuration;
+/// mask files are skipped only when they are used
 pub(crate) fn pairs_from_paths(
     left_path: &Path,
     right_path: &Path,
-    filter_name: Option<&str>,
+    config: &CompareConfig,
 ) -> crate::Result<Vec<Pair>> {
     if !left_path.is_dir() {
         return Err(crate::Error::NotDirectory(left_path.to_path_buf()));
@@ -28,26 +46,55 @@ pub(crate) fn pairs_from_paths(
     if !right_path.is_dir() {
         return Err(crate::Error::NotDirectory(right_path.to_path_buf()));
     }
-    let mut names: Vec<_> = list_image_dir_names(left_path)?.collect();
-    names.extend(list_image_dir_names(right_path)?);
+    let mut names: Vec<PathBuf> = if config.recursive {
+        let mut names = list_image_dir_recursive(left_path)?;
+        names.extend(list_image_dir_recursive(right_path)?);
+        names
+    } else {
+        list_image_dir_names(left_path)?
+            .chain(list_image_dir_names(right_path)?)
+            .map(PathBuf::from)
+            .collect()
+    };
     names.retain(|filename| {
-        filter_name
-            .map(|f| filename.to_string_lossy().contains(f))
-            .unwrap_or(true)
+        !(config.use_mask_files && is_mask_file(filename))
+            && config.name_filter.is_match(&path_title(filename))
     });
-    names.sort_unstable();
+    // Sorted by directory first, so images in the same directory stay together
+    names.sort_unstable_by(|a, b| (a.parent(), a.file_name()).cmp(&(b.parent(), b.file_name())));
     names.dedup();
     Ok(names
         .into_iter()
         .map(|name| {
             let left = left_path.join(&name);
             let right = right_path.join(&name);
-            Pair::new(
-                name.into_string()
-                    .unwrap_or_else(|name| name.to_string_lossy().into_owned()),
-                left,
-                right,
-            )
+            Pair::new(path_title(&name), left, right)
         })
         .collect())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbaImage;
+
+    #[test]
+    fn mask_files_skipped_only_when_used() {
+        let dir = std::env::temp_dir().join("kompari-pairs-mask-files");
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in ["a.png", "a.mask.png"] {
+            RgbaImage::new(2, 2).save(dir.join(name)).unwrap();
+        }
+        let titles = |config: &CompareConfig| {
+            pairs_from_paths(&dir, &dir, config)
+                .unwrap()
+                .into_iter()
+                .map(|pair| pair.title)
+                .collect::<Vec<_>>()
+        };
+        let mut config = CompareConfig::default();
+        assert_eq!(titles(&config), ["a.mask.png", "a.png"]);
+        config.set_use_mask_files(true);
+        assert_eq!(titles(&config), ["a.png"]);
+    }
+}
@@ -0,0 +1,136 @@
+// Copyright 2024 the Kompari Authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Clusters of different pixels.
+
+use crate::Rectangle;
+use std::cmp::Reverse;
+use std::collections::VecDeque;
+
+/// Different pixels closer than this distance (in both axes) belong to the same region
+const REGION_GAP: i64 = 3;
+
+/// Connected cluster of different pixels
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DifferenceRegion {
+    pub bounding_box: Rectangle,
+    /// Number of different pixels in the region
+    pub n_pixels: u64,
+}
+
+/// Finds regions of pixels marked in `mask` (row-major, `width` x `height`),
+/// sorted from the largest number of pixels
+pub(crate) fn find_regions(mask: &[bool], width: u32, height: u32) -> Vec<DifferenceRegion> {
+    let (w, h) = (width as i64, height as i64);
+    let mut visited = vec![false; mask.len()];
+    let mut queue = VecDeque::new();
+    let mut regions = Vec::new();
+    for start in 0..mask.len() {
+        if !mask[start] || visited[start] {
+            continue;
+        }
+        visited[start] = true;
+        queue.push_back(start);
+        let (mut x0, mut y0, mut x1, mut y1) = (w, h, 0, 0);
+        let mut n_pixels = 0;
+        while let Some(index) = queue.pop_front() {
+            let (x, y) = (index as i64 % w, index as i64 / w);
+            n_pixels += 1;
+            x0 = x0.min(x);
+            y0 = y0.min(y);
+            x1 = x1.max(x);
+            y1 = y1.max(y);
+            for ny in (y - REGION_GAP).max(0)..=(y + REGION_GAP).min(h - 1) {
+                for nx in (x - REGION_GAP).max(0)..=(x + REGION_GAP).min(w - 1) {
+                    let neighbor = (ny * w + nx) as usize;
+                    if mask[neighbor] && !visited[neighbor] {
+                        visited[neighbor] = true;
+                        queue.push_back(neighbor);
+                    }
+                }
+            }
+        }
+        regions.push(DifferenceRegion {
+            bounding_box: Rectangle {
+                x: x0 as u32,
+                y: y0 as u32,
+                width: (x1 - x0 + 1) as u32,
+                height: (y1 - y0 + 1) as u32,
+            },
+            n_pixels,
+        });
+    }
+    regions.sort_by_key(|region| Reverse(region.n_pixels));
+    regions
+}
+
+/// Smallest rectangle containing all regions
+pub(crate) fn bounding_box(regions: &[DifferenceRegion]) -> Option<Rectangle> {
+    let (x0, y0, x1, y1) =
+        regions
+            .iter()
+            .fold((u32::MAX, u32::MAX, 0, 0), |(x0, y0, x1, y1), region| {
+                let r = &region.bounding_box;
+                (
+                    x0.min(r.x),
+                    y0.min(r.y),
+                    x1.max(r.x + r.width),
+                    y1.max(r.y + r.height),
+                )
+            });
+    (!regions.is_empty()).then(|| Rectangle {
+        x: x0,
+        y: y0,
+        width: x1 - x0,
+        height: y1 - y0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_regions() {
+        let (width, height) = (20, 10);
+        let mut mask = vec![false; (width * height) as usize];
+        for (x, y) in [(1, 1), (2, 2), (4, 1), (15, 8), (16, 8), (17, 9)] {
+            mask[(y * width + x) as usize] = true;
+        }
+        let regions = find_regions(&mask, width, height);
+        assert_eq!(
+            regions,
+            vec![
+                DifferenceRegion {
+                    bounding_box: Rectangle {
+                        x: 1,
+                        y: 1,
+                        width: 4,
+                        height: 2
+                    },
+                    n_pixels: 3
+                },
+                DifferenceRegion {
+                    bounding_box: Rectangle {
+                        x: 15,
+                        y: 8,
+                        width: 3,
+                        height: 2
+                    },
+                    n_pixels: 3
+                },
+            ]
+        );
+        assert_eq!(
+            bounding_box(&regions),
+            Some(Rectangle {
+                x: 1,
+                y: 1,
+                width: 17,
+                height: 9
+            })
+        );
+        assert_eq!(bounding_box(&[]), None);
+    }
+}
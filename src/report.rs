@@ -1,40 +1,157 @@
 // Copyright 2024 the Kompari Authors
 // SPDX-License-Identifier: Apache-2.0 OR MIT
 
-use crate::difference::{Difference, ImageInfoResult, PairResult, Size};
-use crate::ReportConfig;
+use crate::difference::{
+    ContentDifference, DiffImage, Difference, ImageInfoResult, PairResult, Size,
+};
+use crate::mask::{mask_overlay_image, IgnoreMask};
+use crate::ACCEPT_LIST_FILENAME;
+use crate::{ImageDiff, Rectangle, ReportConfig, Reporter, Summary};
 use base64::prelude::*;
 use chrono::SubsecRound;
+use image::{GenericImageView, ImageFormat, RgbaImage};
 use maud::{html, Markup, PreEscaped, DOCTYPE};
 use std::fs::File;
-use std::io::{Cursor, Write};
-use std::path::Path;
+use std::io::{BufWriter, Cursor, Write};
+use std::path::{Path, PathBuf};
 
 const ICON: &[u8] = include_bytes!("../docs/logo_small.png");
 const IMAGE_SIZE_LIMIT: u32 = 400;
+const MAX_OUTLINED_REGIONS: usize = 100;
+const MAX_DETAIL_REGIONS: usize = 5;
+/// Target size of magnified change details in pixels
+const DETAIL_SIZE: u32 = 200;
+const DETAIL_MAX_SCALE: u32 = 16;
+const DETAIL_MIN_SIZE: u32 = 16;
+const DETAIL_PADDING: u32 = 4;
 
-fn embed_png_url(data: &[u8]) -> String {
-    let mut url = "data:image/png;base64,".to_string();
+fn embed_url(data: &[u8], mime_type: &str) -> String {
+    let mut url = format!("data:{mime_type};base64,");
     url.push_str(&base64::engine::general_purpose::STANDARD.encode(data));
     url
 }
 
+fn embed_png_url(data: &[u8]) -> String {
+    embed_url(data, "image/png")
+}
+
+pub(crate) fn encode_png(image: &RgbaImage) -> Vec<u8> {
+    let mut data = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut data), ImageFormat::Png)
+        .unwrap();
+    data
+}
+
+/// Hatched ignored area and outlines of changed regions placed over an image
+fn render_overlays(pair_diff: &PairResult) -> crate::Result<Markup> {
+    let content = match &pair_diff.difference {
+        Difference::WithinTolerance(content) | Difference::Content(content) => Some(content),
+        _ => None,
+    };
+    let mask = pair_diff
+        .ignore_mask
+        .as_ref()
+        .map(IgnoreMask::image)
+        .transpose()?;
+    Ok(html! {
+        @if let Some(mask) = &mask {
+            img class="mask-overlay" src=(embed_png_url(&encode_png(&mask_overlay_image(mask))));
+        }
+        @if let Some(content) = content.filter(|c| !c.regions.is_empty()) {
+            @let Size { width, height } = content.size;
+            // Small regions would be invisible in the thumbnail, so outlines are padded
+            @let padding = (width.max(height) / 100).max(2);
+            svg class="region-overlay" viewBox=(format!("0 0 {width} {height}")) preserveAspectRatio="none" {
+                @for region in content.regions.iter().take(MAX_OUTLINED_REGIONS) {
+                    @let r = padded_rectangle(&region.bounding_box, padding, width, height);
+                    rect x=(r.x) y=(r.y) width=(r.width) height=(r.height) {}
+                }
+            }
+        }
+    })
+}
+
+fn padded_rectangle(rect: &Rectangle, padding: u32, width: u32, height: u32) -> Rectangle {
+    let x = rect.x.saturating_sub(padding);
+    let y = rect.y.saturating_sub(padding);
+    Rectangle {
+        x,
+        y,
+        width: (rect.x + rect.width + padding).min(width) - x,
+        height: (rect.y + rect.height + padding).min(height) - y,
+    }
+}
+
+fn render_detail_crop(image: &RgbaImage, rect: &Rectangle) -> Markup {
+    let crop = image
+        .view(rect.x, rect.y, rect.width, rect.height)
+        .to_image();
+    let scale = (DETAIL_SIZE / rect.width.max(rect.height)).clamp(1, DETAIL_MAX_SCALE);
+    html! {
+        img class="zoom detail" src=(embed_png_url(&encode_png(&crop))) width=(rect.width * scale) height=(rect.height * scale) onclick="openImageDialog(this)";
+    }
+}
+
+/// Cropped and magnified views of the largest changed regions
+fn render_change_details(
+    config: &ReportConfig,
+    pair_diff: &PairResult,
+    content: &ContentDifference,
+    diff_image: &RgbaImage,
+) -> crate::Result<Markup> {
+    if content.regions.is_empty() {
+        return Ok(html! {});
+    }
+    let left = image::open(&pair_diff.pair.left)?.into_rgba8();
+    let right = image::open(&pair_diff.pair.right)?.into_rgba8();
+    let Size { width, height } = content.size;
+    Ok(html! {
+        details class="change-details" {
+            summary { "Change details (" (content.regions.len()) " regions)" }
+            @for (i, region) in content.regions.iter().take(MAX_DETAIL_REGIONS).enumerate() {
+                @let r = &region.bounding_box;
+                @let crop = padded_rectangle(&Rectangle { width: r.width.max(DETAIL_MIN_SIZE), height: r.height.max(DETAIL_MIN_SIZE), ..*r }, DETAIL_PADDING, width, height);
+                div class="change-detail" {
+                    div class="detail-title" {
+                        (format!("Region {}: {}x{} at ({}, {}), {} pixels", i + 1, r.width, r.height, r.x, r.y, region.n_pixels))
+                    }
+                    div class="detail-images" {
+                        div { div class="stat-label" { (config.left_title) } (render_detail_crop(&left, &crop)) }
+                        div { div class="stat-label" { (config.right_title) } (render_detail_crop(&right, &crop)) }
+                        div { div class="stat-label" { "Difference" } (render_detail_crop(diff_image, &crop)) }
+                    }
+                }
+            }
+        }
+    })
+}
+
 fn render_image(
     config: &ReportConfig,
     image_info: &ImageInfoResult,
     path: &Path,
+    overlays: &Markup,
 ) -> crate::Result<Markup> {
     Ok(match image_info {
         ImageInfoResult::Loaded(info) => {
             let (w, h) = html_size(&info.size, IMAGE_SIZE_LIMIT);
             let path = if config.embed_images {
                 let image_data = std::fs::read(path)?;
-                embed_png_url(&image_data)
+                let mime_type = ImageFormat::from_path(path)
+                    .map(|format| format.to_mime_type())
+                    .unwrap_or("image/png");
+                embed_url(&image_data, mime_type)
             } else {
                 path.display().to_string()
             };
             html! {
-                img class="zoom" src=(path) width=[w] height=[h] onclick="openImageDialog(this)";
+                div class="image-viewport" {
+                    div class="image-frame" {
+                        img class="zoom" src=(path) width=[w] height=[h] onclick="openImageDialog(this)";
+                        (overlays)
+                    }
+                }
             }
         }
         ImageInfoResult::Missing => {
@@ -54,23 +171,22 @@ pub fn html_size(size: &Size, size_limit: u32) -> (Option<u32>, Option<u32>) {
     }
 }
 
-fn render_difference_image(difference: &Difference) -> Markup {
-    match difference {
-        Difference::None
-        | Difference::LoadError
-        | Difference::MissingFile
-        | Difference::SizeMismatch => html!("N/A"),
-        Difference::Content { diff_image, .. } => {
-            let (w, h) = html_size(
-                &Size::new(diff_image.width(), diff_image.height()),
-                IMAGE_SIZE_LIMIT,
-            );
-            let mut data = Vec::new();
-            diff_image
-                .write_to(&mut Cursor::new(&mut data), image::ImageFormat::Png)
-                .unwrap();
-            html! {
-                img class="zoom" src=(embed_png_url(&data)) width=[w] height=[h] onclick="openImageDialog(this)";
+fn render_difference_image(
+    config: &ReportConfig,
+    content: &ContentDifference,
+    diff_image: &RgbaImage,
+    overlays: &Markup,
+) -> Markup {
+    let (w, h) = html_size(&content.size, IMAGE_SIZE_LIMIT);
+    let src = match &content.diff_image {
+        DiffImage::File(path) if !config.embed_images => path.display().to_string(),
+        _ => embed_png_url(&encode_png(diff_image)),
+    };
+    html! {
+        div class="image-viewport" {
+            div class="image-frame" {
+                img class="zoom" src=(src) width=[w] height=[h] onclick="openImageDialog(this)";
+                (overlays)
             }
         }
     }
@@ -93,59 +209,159 @@ fn render_stat_item(label: &str, value_type: &str, value: &str) -> Markup {
 fn render_difference_info(config: &ReportConfig, pair_diff: &PairResult) -> Markup {
     match &pair_diff.difference {
         Difference::None => render_stat_item("Status", "ok", "Match"),
-        Difference::LoadError => render_stat_item("Status", "error", "Loading error"),
+        Difference::LoadError(error) => html! {
+            (render_stat_item("Status", "error", "Loading error"))
+            (render_stat_item("Error", "", error))
+        },
         Difference::MissingFile => render_stat_item("Status", "error", "Missing file"),
         Difference::SizeMismatch => html! {
             (render_stat_item("Status", "error", "Size mismatch"))
             (render_stat_item(&format!("{} size", config.left_title), "", &pair_diff.left_info.info().unwrap().size.to_string()))
             (render_stat_item(&format!("{} size", config.right_title), "", &pair_diff.right_info.info().unwrap().size.to_string()))
         },
-        Difference::Content {
-            n_different_pixels,
-            distance_sum,
-            ..
-        } => {
-            let size = &pair_diff.left_info.info().unwrap().size;
-            let n_pixels = size.width as f32 * size.height as f32;
-            let pct = *n_different_pixels as f32 / n_pixels * 100.0;
-            let distance_sum = *distance_sum as f32 / 255.0; // Normalize
-            let avg_color_distance = distance_sum / n_pixels;
-            html! {
-                (render_stat_item("Different pixels", "warning", &format!("{n_different_pixels} ({pct:.1}%)")))
-                (render_stat_item("Color distance", "", &format!("{distance_sum:.3}")))
-                (render_stat_item("Avg. color distance", "", &format!("{avg_color_distance:.4}")))
-            }
+        Difference::WithinTolerance(content) => html! {
+            (render_stat_item("Status", "tolerated", "Within tolerance"))
+            (render_content_stats(pair_diff, content, "tolerated"))
+        },
+        Difference::Content(content) => render_content_stats(pair_diff, content, "warning"),
+    }
+}
+
+fn render_content_stats(
+    pair_diff: &PairResult,
+    content: &ContentDifference,
+    value_type: &str,
+) -> Markup {
+    let size = &pair_diff.left_info.info().unwrap().size;
+    let n_pixels = size.width as f32 * size.height as f32;
+    let n_different_pixels = content.n_different_pixels;
+    let pct = n_different_pixels as f32 / n_pixels * 100.0;
+    let distance_sum = content.distance_sum as f32 / 255.0; // Normalize
+    let avg_color_distance = distance_sum / n_pixels;
+    html! {
+        (render_stat_item("Different pixels", value_type, &format!("{n_different_pixels} ({pct:.1}%)")))
+        @if content.n_antialiased_pixels > 0 {
+            (render_stat_item("Anti-aliased pixels", "", &content.n_antialiased_pixels.to_string()))
+        }
+        @if let Some(r) = &content.bounding_box {
+            (render_stat_item("Changed area", "", &format!("{}x{} at ({}, {})", r.width, r.height, r.x, r.y)))
+        }
+        (render_stat_item("Color distance", "", &format!("{distance_sum:.3}")))
+        (render_stat_item("Avg. color distance", "", &format!("{avg_color_distance:.4}")))
+        @if let Some(ssim) = content.ssim {
+            (render_stat_item("SSIM", "", &format!("{ssim:.4}")))
         }
+        (render_stat_item("Max. ΔE", "", &format!("{:.2}", content.max_delta_e)))
+        (render_stat_item("Mean ΔE", "", &format!("{:.4}", content.mean_delta_e)))
     }
 }
 
-fn render_pair_diff(config: &ReportConfig, pair_diff: &PairResult) -> crate::Result<Markup> {
+/// Id of the report entry of an image pair, usable as URL fragment.
+/// Bytes other than ASCII letters, digits, '-' and '.' are written as `_XX`
+/// (hex), so different titles have different ids.
+pub(crate) fn entry_anchor(title: &str) -> String {
+    let mut anchor = String::from("diff-");
+    for byte in title.bytes() {
+        if byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'.' {
+            anchor.push(byte as char);
+        } else {
+            anchor.push_str(&format!("_{byte:02X}"));
+        }
+    }
+    anchor
+}
+
+pub(crate) fn render_pair_diff(
+    config: &ReportConfig,
+    pair_diff: &PairResult,
+) -> crate::Result<Markup> {
+    let overlays = render_overlays(pair_diff)?;
+    // The difference image may have to be created again, so it is loaded only once
+    let (difference_image, details) = match pair_diff.difference.content() {
+        Some(content) => {
+            let diff_image = content.diff_image.load()?;
+            (
+                render_difference_image(config, content, &diff_image, &overlays),
+                render_change_details(config, pair_diff, content, &diff_image)?,
+            )
+        }
+        None => (html!("N/A"), html! {}),
+    };
     Ok(html! {
-        div class="diff-entry" {
-            h2 {(pair_diff.pair.title)};
+        div class="diff-entry" id=(entry_anchor(&pair_diff.pair.title)) data-title=(pair_diff.pair.title) {
+            h2 {
+                (pair_diff.pair.title)
+                @if pair_diff.is_blessable() && config.review_token.is_some() {
+                    span class="review-actions" {
+                        button type="button" onclick="reviewAction(this, 'accept')" { "Accept" }
+                        button type="button" onclick="reviewAction(this, 'reject')" { "Reject" }
+                    }
+                } @else if pair_diff.is_blessable() {
+                    label class="accept-toggle" {
+                        input type="checkbox" class="accept" value=(pair_diff.pair.title) onchange="updateAcceptList()";
+                        " Accept"
+                    }
+                }
+            }
             div class="comparison-container" {
                 div class="image-container" {
                     div class="stats-container" {
-                        (render_difference_info(config, &pair_diff))
+                        (render_difference_info(config, pair_diff))
                     }
                     div class="image-box" {
                         h3 { (config.left_title) }
-                        (render_image(config, &pair_diff.left_info, &pair_diff.pair.left)?)
+                        (render_image(config, &pair_diff.left_info, &pair_diff.pair.left, &overlays)?)
                     }
                     div class="image-box" {
                         h3 { (config.right_title) }
-                        (render_image(config, &pair_diff.right_info, &pair_diff.pair.right)?)
+                        (render_image(config, &pair_diff.right_info, &pair_diff.pair.right, &overlays)?)
                     }
                     div class="image-box" {
                         h3 { "Difference"}
-                        (render_difference_image(&pair_diff.difference))
+                        (difference_image)
                     }
                 }
             }
+            @if pair_diff.left_info.info().is_some() && pair_diff.right_info.info().is_some()
+                && !matches!(pair_diff.difference, Difference::SizeMismatch) {
+                (render_viewer_controls())
+            }
+            (details)
         }
     })
 }
 
+/// Entry of an image pair whose images were removed during the review
+#[cfg(feature = "xtask-cli")]
+pub(crate) fn render_removed_entry(title: &str) -> Markup {
+    html! {
+        div class="diff-entry" id=(entry_anchor(title)) data-title=(title) {
+            h2 { (title) }
+            div class="stats-container" {
+                (render_stat_item("Status", "", "Removed"))
+            }
+        }
+    }
+}
+
+/// Controls switching between the side-by-side view and the interactive
+/// comparison views; the comparison view itself is built by `setViewMode`
+/// from the images already present in the entry
+fn render_viewer_controls() -> Markup {
+    html! {
+        div class="viewer-controls" {
+            span class="stat-label" { "View:" }
+            button type="button" class="active" data-mode="side" onclick="setViewMode(this)" { "Side by side" }
+            button type="button" data-mode="swipe" onclick="setViewMode(this)" { "Swipe" }
+            button type="button" data-mode="onion" onclick="setViewMode(this)" { "Onion skin" }
+            button type="button" data-mode="blink" onclick="setViewMode(this)" { "Blink" }
+            button type="button" onclick="resetZoom(this)" { "Reset zoom" }
+            span class="viewer-hint" { "Ctrl+scroll (or pinch) over an image to zoom, drag to pan" }
+        }
+        div class="compare-view" hidden {}
+    }
+}
+
 const CSS_STYLE: &str = "
 body {
     font-family: Roboto, sans-serif;
@@ -180,6 +396,83 @@ body {
     box-shadow: 0 2px 4px rgba(0,0,0,0.1);
 }
 
+.summary h2 {
+    margin-top: 0;
+    color: #2d3748;
+}
+
+.compared-dirs {
+    color: #4a5568;
+}
+
+.summary-counts {
+    display: flex;
+    flex-wrap: wrap;
+    gap: 20px;
+}
+
+.accept-bar {
+    display: flex;
+    flex-wrap: wrap;
+    align-items: center;
+    gap: 8px;
+    margin-top: 15px;
+}
+
+.accept-bar button:disabled {
+    cursor: default;
+    color: #94a3b8;
+}
+
+.review-actions {
+    float: right;
+    display: flex;
+    gap: 8px;
+}
+
+.review-actions button {
+    padding: 4px 10px;
+    border: 1px solid #cbd5e1;
+    border-radius: 4px;
+    background: #f8fafc;
+    color: #2d3748;
+    font-size: 1rem;
+    cursor: pointer;
+}
+
+.accept-toggle {
+    float: right;
+    font-size: 1rem;
+    font-weight: normal;
+    color: #4a5568;
+    cursor: pointer;
+}
+
+.toc summary {
+    cursor: pointer;
+    color: #4a5568;
+    font-weight: 600;
+}
+
+.toc table {
+    margin-top: 10px;
+    border-collapse: collapse;
+}
+
+.toc td {
+    padding: 2px 20px 2px 0;
+}
+
+.toc td.stat-value {
+    font-size: 0.875rem;
+}
+
+.group-header {
+    margin: 30px 0 15px 0;
+    color: #2d3748;
+    font-family: monospace;
+}
+
 .diff-entry {
     background: #fff;
     margin-bottom: 30px;
@@ -226,6 +519,157 @@ body {
     border-radius: 4px;
 }
 
+.image-frame {
+    position: relative;
+    display: inline-block;
+    max-width: 100%;
+}
+
+.image-frame img.zoom {
+    display: block;
+}
+
+.image-frame img.mask-overlay, .image-frame svg.region-overlay {
+    position: absolute;
+    left: 0;
+    top: 0;
+    width: 100%;
+    height: 100%;
+    pointer-events: none;
+    border-color: transparent;
+    background: none;
+    image-rendering: pixelated;
+}
+
+.image-viewport {
+    display: inline-block;
+    max-width: 100%;
+    overflow: hidden;
+    vertical-align: top;
+}
+
+.image-viewport > * {
+    transform-origin: 0 0;
+}
+
+.zoomed .image-viewport {
+    cursor: grab;
+}
+
+.zoomed .image-viewport img {
+    image-rendering: pixelated;
+}
+
+.zoomed img.zoom:hover {
+    transform: none;
+}
+
+.viewer-controls {
+    display: flex;
+    flex-wrap: wrap;
+    align-items: center;
+    gap: 8px;
+    margin-top: 15px;
+}
+
+.viewer-controls button {
+    padding: 4px 10px;
+    border: 1px solid #cbd5e1;
+    border-radius: 4px;
+    background: #f8fafc;
+    color: #2d3748;
+    cursor: pointer;
+}
+
+.viewer-controls button.active {
+    background: #2d3748;
+    color: #fff;
+}
+
+.viewer-hint {
+    font-size: 0.875rem;
+    color: #94a3b8;
+}
+
+.compare-view {
+    margin-top: 15px;
+}
+
+.compare-stage {
+    position: relative;
+}
+
+.compare-stage img {
+    display: block;
+    border: 1px solid #e2e8f0;
+    background: repeating-conic-gradient(#d4d4d4 0% 25%, #ffffff 0% 50%) 50% / 16px 16px;
+}
+
+.compare-top {
+    position: absolute;
+    left: 0;
+    top: 0;
+    height: 100%;
+    overflow: hidden;
+    border-right: 2px solid #e11d48;
+}
+
+.compare-top.blended {
+    border-right: none;
+}
+
+.compare-toolbar {
+    display: flex;
+    align-items: center;
+    gap: 10px;
+    margin-top: 8px;
+    font-size: 0.875rem;
+    color: #4a5568;
+}
+
+.region-overlay rect {
+    fill: none;
+    stroke: #e11d48;
+    stroke-width: 2;
+    vector-effect: non-scaling-stroke;
+}
+
+.change-details {
+    margin-top: 15px;
+}
+
+.change-details summary {
+    cursor: pointer;
+    color: #4a5568;
+    font-weight: 600;
+}
+
+.change-detail {
+    margin-top: 10px;
+}
+
+.detail-title {
+    font-size: 0.875rem;
+    margin-bottom: 4px;
+}
+
+.detail-images {
+    display: flex;
+    gap: 20px;
+    flex-wrap: wrap;
+}
+
+img.detail {
+    border: 1px solid #e2e8f0;
+    image-rendering: pixelated;
+    background: repeating-conic-gradient(#d4d4d4 0% 25%, #ffffff 0% 50%) 50% / 16px 16px;
+}
+
+.image-box img, .zoomed-image {
+    /* Checkerboard backdrop to make transparency visible */
+    background: repeating-conic-gradient(#d4d4d4 0% 25%, #ffffff 0% 50%) 50% / 16px 16px;
+}
+
 .stats-container {
     width: 200px;
     flex-shrink: 0;
@@ -255,6 +699,10 @@ body {
     color: #77d906;
 }
 
+.stat-value.tolerated {
+    color: #0891b2;
+}
+
 .stat-value.warning {
     color: #d97706;
 }
@@ -336,41 +784,490 @@ function closeImageDialog() {
 document.getElementById('imageDialog').addEventListener('click', function(event) {
     closeImageDialog();
 });
+
+// Zoom and pan state shared by all images of a diff entry
+const viewStates = new Map();
+const MAX_ZOOM = 64;
+const COMPARE_SIZE_LIMIT = 800;
+const BLINK_INTERVAL_MS = 500;
+
+function entryState(entry) {
+    if (!viewStates.has(entry)) {
+        viewStates.set(entry, { scale: 1, x: 0, y: 0, timer: null });
+    }
+    return viewStates.get(entry);
+}
+
+function applyTransform(entry) {
+    const state = entryState(entry);
+    entry.querySelectorAll('.image-viewport > *').forEach(element => {
+        element.style.transform = `translate(${state.x}px, ${state.y}px) scale(${state.scale})`;
+    });
+    entry.classList.toggle('zoomed', state.scale > 1);
+}
+
+function resetZoom(button) {
+    const entry = button.closest('.diff-entry');
+    Object.assign(entryState(entry), { scale: 1, x: 0, y: 0 });
+    applyTransform(entry);
+}
+
+function initViewport(viewport) {
+    const entry = viewport.closest('.diff-entry');
+    let drag = null;
+    let suppressClick = false;
+    viewport.addEventListener('wheel', event => {
+        // Plain scrolling scrolls the page; pinch gestures are reported with ctrlKey
+        if (!event.ctrlKey && !event.altKey) {
+            return;
+        }
+        event.preventDefault();
+        const state = entryState(entry);
+        const rect = viewport.getBoundingClientRect();
+        const px = event.clientX - rect.left;
+        const py = event.clientY - rect.top;
+        const factor = event.deltaY < 0 ? 1.25 : 0.8;
+        const scale = Math.min(Math.max(state.scale * factor, 1), MAX_ZOOM);
+        // Keep the pixel under the cursor in place
+        state.x = scale === 1 ? 0 : px - (px - state.x) * scale / state.scale;
+        state.y = scale === 1 ? 0 : py - (py - state.y) * scale / state.scale;
+        state.scale = scale;
+        applyTransform(entry);
+    }, { passive: false });
+    viewport.addEventListener('pointerdown', event => {
+        const state = entryState(entry);
+        if (state.scale === 1) {
+            return;
+        }
+        event.preventDefault();
+        drag = { x: event.clientX - state.x, y: event.clientY - state.y, moved: false };
+    });
+    viewport.addEventListener('pointermove', event => {
+        if (!drag) {
+            return;
+        }
+        const state = entryState(entry);
+        state.x = event.clientX - drag.x;
+        state.y = event.clientY - drag.y;
+        drag.moved = true;
+        applyTransform(entry);
+    });
+    const endDrag = () => {
+        suppressClick = drag !== null && drag.moved;
+        drag = null;
+    };
+    viewport.addEventListener('pointerup', endDrag);
+    viewport.addEventListener('pointerleave', endDrag);
+    // Do not open the image dialog at the end of panning
+    viewport.addEventListener('click', event => {
+        if (suppressClick) {
+            event.stopPropagation();
+            suppressClick = false;
+        }
+    }, true);
+}
+
+function comparisonImage(source, width, height) {
+    const img = document.createElement('img');
+    img.src = source.src;
+    img.width = width;
+    img.height = height;
+    img.draggable = false;
+    return img;
+}
+
+function setViewMode(button) {
+    const entry = button.closest('.diff-entry');
+    const mode = button.dataset.mode;
+    const state = entryState(entry);
+    entry.querySelectorAll('.viewer-controls button[data-mode]').forEach(b => {
+        b.classList.toggle('active', b === button);
+    });
+    clearInterval(state.timer);
+    const view = entry.querySelector('.compare-view');
+    view.replaceChildren();
+    view.hidden = mode === 'side';
+    if (mode === 'side') {
+        return;
+    }
+
+    const [left, right] = entry.querySelectorAll('.image-box img.zoom');
+    const [leftTitle, rightTitle] = Array.from(entry.querySelectorAll('.image-box h3'), h => h.textContent);
+    const ratio = Math.min(1, COMPARE_SIZE_LIMIT / Math.max(left.naturalWidth, left.naturalHeight));
+    const width = Math.round(left.naturalWidth * ratio);
+    const height = Math.round(left.naturalHeight * ratio);
+
+    const viewport = document.createElement('div');
+    viewport.className = 'image-viewport';
+    const stage = document.createElement('div');
+    stage.className = 'compare-stage';
+    const top = document.createElement('div');
+    top.className = 'compare-top';
+    top.appendChild(comparisonImage(left, width, height));
+    stage.append(comparisonImage(right, width, height), top);
+    viewport.appendChild(stage);
+
+    const toolbar = document.createElement('div');
+    toolbar.className = 'compare-toolbar';
+    const label = document.createElement('span');
+    if (mode === 'blink') {
+        top.classList.add('blended');
+        top.style.width = '100%';
+        label.textContent = leftTitle;
+        state.timer = setInterval(() => {
+            top.hidden = !top.hidden;
+            label.textContent = top.hidden ? rightTitle : leftTitle;
+        }, BLINK_INTERVAL_MS);
+        toolbar.append(label);
+    } else {
+        const slider = document.createElement('input');
+        slider.type = 'range';
+        slider.min = 0;
+        slider.max = 100;
+        slider.value = 50;
+        const update = () => {
+            if (mode === 'swipe') {
+                top.style.width = `${slider.value}%`;
+            } else {
+                top.classList.add('blended');
+                top.style.width = '100%';
+                top.style.opacity = 1 - slider.value / 100;
+            }
+        };
+        slider.addEventListener('input', update);
+        update();
+        const rightLabel = document.createElement('span');
+        label.textContent = leftTitle;
+        rightLabel.textContent = rightTitle;
+        toolbar.append(label, slider, rightLabel);
+    }
+    view.append(viewport, toolbar);
+    initViewport(viewport);
+    applyTransform(entry);
+}
+
+function acceptedTitles() {
+    return Array.from(document.querySelectorAll('input.accept:checked'), input => input.value);
+}
+
+function updateAcceptList() {
+    const count = acceptedTitles().length;
+    document.getElementById('acceptCount').textContent = count;
+    document.getElementById('downloadAccept').disabled = count === 0;
+}
+
+function setAllAccepted(accepted) {
+    document.querySelectorAll('input.accept').forEach(input => {
+        input.checked = accepted;
+    });
+    updateAcceptList();
+}
+
+function downloadAcceptList(button) {
+    const content = acceptedTitles().map(title => title + '\\n').join('');
+    const link = document.createElement('a');
+    link.href = URL.createObjectURL(new Blob([content], { type: 'text/plain' }));
+    link.download = button.dataset.filename;
+    link.click();
+    URL.revokeObjectURL(link.href);
+}
+
+// Used by reports served by the review server
+async function reviewAction(button, action) {
+    const entry = button.closest('.diff-entry');
+    entry.querySelectorAll('.review-actions button').forEach(b => {
+        b.disabled = true;
+    });
+    const token = document.querySelector('meta[name=kompari-review-token]').content;
+    const response = await fetch(`/${action}?title=${encodeURIComponent(entry.dataset.title)}`, {
+        method: 'POST',
+        headers: { 'X-Kompari-Token': token },
+    });
+    if (!response.ok) {
+        alert(await response.text());
+        entry.querySelectorAll('.review-actions button').forEach(b => {
+            b.disabled = false;
+        });
+        return;
+    }
+    const template = document.createElement('template');
+    template.innerHTML = await response.text();
+    const updated = template.content.firstElementChild;
+    entry.replaceWith(updated);
+    updated.querySelectorAll('.image-viewport').forEach(initViewport);
+}
+
+// The summary is written after all entries, it replaces the placeholder at the top
+function placeSummary() {
+    const placeholder = document.getElementById('summaryPlaceholder');
+    if (placeholder) {
+        placeholder.replaceWith(document.getElementById('summary'));
+    }
+}
+
+document.addEventListener('DOMContentLoaded', () => {
+    document.querySelectorAll('.image-viewport').forEach(initViewport);
+    // Browsers may restore checkboxes when the page is reloaded
+    if (document.getElementById('downloadAccept')) {
+        updateAcceptList();
+    }
+});
 ";
 
-pub(crate) fn create_html_report(
+fn status_class(difference: &Difference) -> &'static str {
+    match difference {
+        Difference::None => "ok",
+        Difference::WithinTolerance(_) => "tolerated",
+        Difference::Content(_) => "warning",
+        Difference::MissingFile | Difference::LoadError(_) | Difference::SizeMismatch => "error",
+    }
+}
+
+/// Number of entries up to which the table of contents is expanded by default
+const TOC_OPEN_LIMIT: usize = 50;
+
+/// Entry of the table of contents
+struct ContentsEntry {
+    title: String,
+    status: &'static str,
+    class: &'static str,
+}
+
+fn render_summary(
     config: &ReportConfig,
-    diffs: &[PairResult],
-    output: &Path,
-) -> crate::Result<()> {
-    let now = chrono::Local::now().round_subsecs(0);
-    let report = html! {
-        (DOCTYPE)
-        html {
+    summary: &Summary,
+    contents: &[ContentsEntry],
+    has_blessable: bool,
+    directories: &[(PathBuf, PathBuf)],
+) -> Markup {
+    let missing_left = format!("Missing in {}", config.left_title);
+    let missing_right = format!("Missing in {}", config.right_title);
+    let counts = [
+        ("Match", "ok", summary.n_match),
+        ("Within tolerance", "tolerated", summary.n_within_tolerance),
+        ("Different content", "warning", summary.n_content),
+        ("Size mismatch", "error", summary.n_size_mismatch),
+        (missing_left.as_str(), "error", summary.n_missing_left),
+        (missing_right.as_str(), "error", summary.n_missing_right),
+        ("Loading errors", "error", summary.n_load_errors),
+    ];
+    html! {
+        div class="summary" id="summary" {
+            h2 { "Summary" }
+            @for (left, right) in directories {
+                p class="compared-dirs" {
+                    (config.left_title) ": " code { (left.display()) }
+                    br;
+                    (config.right_title) ": " code { (right.display()) }
+                }
+            }
+            div class="summary-counts" {
+                (render_stat_item("Total", "", &summary.n_total().to_string()))
+                @for (label, value_type, count) in counts {
+                    (render_stat_item(label, if count > 0 { value_type } else { "" }, &count.to_string()))
+                }
+            }
+            @if config.review_token.is_none() && has_blessable {
+                div class="accept-bar" {
+                    button type="button" id="downloadAccept" data-filename=(ACCEPT_LIST_FILENAME) onclick="downloadAcceptList(this)" disabled {
+                        "Download accept list (" span id="acceptCount" { "0" } ")"
+                    }
+                    button type="button" onclick="setAllAccepted(true)" { "Accept all" }
+                    button type="button" onclick="setAllAccepted(false)" { "Clear" }
+                    span class="viewer-hint" {
+                        "Pass the list to the " code { "bless --accept-list" } " command to replace images in "
+                        (config.right_title) " by images in " (config.left_title)
+                    }
+                }
+            }
+            @if !contents.is_empty() {
+                details class="toc" open[contents.len() <= TOC_OPEN_LIMIT] {
+                    summary { "Contents" }
+                    table {
+                        @for entry in contents {
+                            tr {
+                                td { a href=(format!("#{}", entry_anchor(&entry.title))) { (entry.title) } }
+                                td class=(format!("stat-value {}", entry.class)) { (entry.status) }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Writes an HTML report entry by entry.
+///
+/// Each entry is written (and flushed) as soon as it is passed to the writer,
+/// so results do not have to be kept in memory and an interrupted report shows
+/// the entries written so far. Only titles are kept for the summary, which is
+/// written by [`finish`](Self::finish) and moved to the top of the page.
+pub struct HtmlReportWriter<'a, W: Write> {
+    config: &'a ReportConfig<'a>,
+    output: W,
+    summary: Summary,
+    contents: Vec<ContentsEntry>,
+    has_blessable: bool,
+    last_directory: Option<String>,
+}
+
+impl<'a> HtmlReportWriter<'a, BufWriter<File>> {
+    /// Creates the report file and writes the head of the report
+    pub fn create(config: &'a ReportConfig<'a>, path: &Path) -> crate::Result<Self> {
+        Self::new(config, BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<'a, W: Write> HtmlReportWriter<'a, W> {
+    /// Writes the head of the report into `output`
+    pub fn new(config: &'a ReportConfig<'a>, mut output: W) -> crate::Result<Self> {
+        let now = chrono::Local::now().round_subsecs(0);
+        let head = html! {
+            (DOCTYPE)
+            // Closed by `finish`
+            (PreEscaped("<html>"))
             head {
                 meta charset="utf-8";
                 meta name="viewport" content="width=device-width, initial-scale=1.0";
                 meta name="generator" content=(format!("Kompari {}", env!("CARGO_PKG_VERSION")));
                 title { "Image diff" }
                 style { (PreEscaped(CSS_STYLE)) }
-                link rel="icon" type="image/png" href=(embed_png_url(&ICON));
-            }
-            body {
-                 div class="header" {
-                    h1 { img class="logo" src=(embed_png_url(ICON)) width="32" height="32"; "Kompari Report" }
-                    p { "Generated on " (now) }
-                }
-                dialog id="imageDialog" {
-                    img id="zoomedImage" class="zoomed-image" src="" alt="Zoomed Image";
-                }
-                script { (PreEscaped(JS_CODE)) }
-                @for pair_diff in diffs {
-                   (render_pair_diff(config, pair_diff)?)
+                link rel="icon" type="image/png" href=(embed_png_url(ICON));
+                @if let Some(token) = config.review_token {
+                    meta name="kompari-review-token" content=(token);
                 }
             }
+            (PreEscaped("<body>"))
+            div class="header" {
+                h1 { img class="logo" src=(embed_png_url(ICON)) width="32" height="32"; "Kompari Report" }
+                p { "Generated on " (now) }
+            }
+            dialog id="imageDialog" {
+                img id="zoomedImage" class="zoomed-image" src="" alt="Zoomed Image";
+            }
+            script { (PreEscaped(JS_CODE)) }
+            div class="summary" id="summaryPlaceholder" {
+                h2 { "Summary" }
+                p { "The report is incomplete, the summary is written when all images are compared." }
+            }
+        };
+        output.write_all(head.into_string().as_bytes())?;
+        output.flush()?;
+        Ok(HtmlReportWriter {
+            config,
+            output,
+            summary: Summary::default(),
+            contents: Vec::new(),
+            has_blessable: false,
+            last_directory: None,
+        })
+    }
+
+    /// Writes the entry of a compared pair; entries are expected in the order of titles,
+    /// a header is written when the directory of the entry changes
+    pub fn write_entry(&mut self, pair_diff: &PairResult) -> crate::Result<()> {
+        let directory = pair_diff.pair.directory();
+        let entry = html! {
+            @if let Some(dir) = directory.filter(|dir| self.last_directory.as_deref() != Some(dir)) {
+                h2 class="group-header" { (dir) "/" }
+            }
+            (render_pair_diff(self.config, pair_diff)?)
+        };
+        self.output.write_all(entry.into_string().as_bytes())?;
+        self.output.flush()?;
+
+        self.last_directory = directory.map(str::to_string);
+        self.summary.add(pair_diff);
+        self.has_blessable |= pair_diff.is_blessable();
+        self.contents.push(ContentsEntry {
+            title: pair_diff.pair.title.clone(),
+            status: pair_diff.difference.status(),
+            class: status_class(&pair_diff.difference),
+        });
+        Ok(())
+    }
+
+    /// Writes the summary of the written entries and completes the report
+    pub fn finish(mut self, directories: &[(PathBuf, PathBuf)]) -> crate::Result<W> {
+        let tail = html! {
+            (render_summary(self.config, &self.summary, &self.contents, self.has_blessable, directories))
+            script { "placeSummary();" }
+            (PreEscaped("</body></html>"))
+        };
+        self.output.write_all(tail.into_string().as_bytes())?;
+        self.output.flush()?;
+        Ok(self.output)
+    }
+}
+
+/// Writes a single HTML file with all results
+pub struct HtmlReporter<'a> {
+    output: &'a Path,
+}
+
+impl<'a> HtmlReporter<'a> {
+    pub fn new(output: &'a Path) -> Self {
+        HtmlReporter { output }
+    }
+}
+
+impl Reporter for HtmlReporter<'_> {
+    fn write_report(&self, image_diff: &ImageDiff, config: &ReportConfig) -> crate::Result<()> {
+        let mut writer = HtmlReportWriter::create(config, self.output)?;
+        for pair_diff in image_diff.results() {
+            writer.write_entry(pair_diff)?;
         }
-    };
-    let mut file = File::create(output)?;
-    file.write_all(report.into_string().as_bytes())?;
-    Ok(())
+        writer.finish(image_diff.directories())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::pair_result;
+
+    #[test]
+    fn anchors_are_unique() {
+        assert_eq!(entry_anchor("a/b.png"), "diff-a_2Fb.png");
+        assert_eq!(entry_anchor("a_b.png"), "diff-a_5Fb.png");
+        assert_eq!(entry_anchor("ä.png"), "diff-_C3_A4.png");
+    }
+
+    #[test]
+    fn summary_and_contents() {
+        let config = ReportConfig::default();
+        let diffs = [
+            pair_result("a_b.png", Difference::None),
+            pair_result("c.png", Difference::LoadError("broken".into())),
+            pair_result("a/b.png", Difference::MissingFile),
+        ];
+        let mut writer = HtmlReportWriter::new(&config, Vec::new()).unwrap();
+        for pair_diff in &diffs {
+            writer.write_entry(pair_diff).unwrap();
+        }
+        let report = String::from_utf8(writer.finish(&[]).unwrap()).unwrap();
+        let count = |label: &str, class: &str, value: usize| {
+            format!(r#"{label}</div><div class="stat-value {class}">{value}</div>"#)
+        };
+        assert!(report.contains(&count("Total", "", 3)));
+        assert!(report.contains(&count("Match", "ok", 1)));
+        assert!(report.contains(&count("Missing in Right image", "error", 1)));
+        assert!(report.contains(&count("Loading errors", "error", 1)));
+        assert!(report.contains(&count("Different content", "", 0)));
+
+        let contents = &report[report.find(r#"class="toc""#).unwrap()..];
+        for (title, status) in [
+            ("a_b.png", "ok\">Match"),
+            ("c.png", "error\">Loading error"),
+            ("a/b.png", "error\">Missing file"),
+        ] {
+            let anchor = entry_anchor(title);
+            assert!(report.contains(&format!(r#"id="{anchor}""#)));
+            assert!(contents.contains(&format!(
+                r##"<a href="#{anchor}">{title}</a></td><td class="stat-value {status}</td>"##
+            )));
+        }
+    }
 }
@@ -0,0 +1,326 @@
+// Copyright 2024 the Kompari Authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+use crate::bless::bless_pair;
+use crate::difference::{compute_pair_diff, PairResult};
+use crate::pair::pairs_from_paths;
+use crate::report::{render_pair_diff, render_removed_entry, HtmlReportWriter};
+use crate::{CompareConfig, Error, ImageDiff, ReportConfig};
+use std::path::Path;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+/// Header with the token of the server run, required by `POST` requests
+const TOKEN_HEADER: &str = "X-Kompari-Token";
+
+/// Serves the report of current images against snapshots on localhost.
+///
+/// Besides the report page (`GET /`) the server handles `POST /accept?title=...`,
+/// which copies the current image over the snapshot, and `POST /reject?title=...`,
+/// which removes the current image. Both respond with the re-rendered report entry,
+/// or with an entry saying the image was removed when no snapshot is left.
+///
+/// Requests with a `Host` other than the server address are refused (DNS rebinding),
+/// `POST` requests have to send the token embedded in the report page, which other
+/// pages open in the browser cannot read.
+pub(crate) struct ReviewServer<'a> {
+    current_path: &'a Path,
+    snapshot_path: &'a Path,
+    compare_config: CompareConfig,
+    token: String,
+}
+
+/// Response body with its status code and content type
+struct Page {
+    status: u16,
+    content_type: &'static str,
+    body: String,
+}
+
+impl Page {
+    fn html(body: String) -> Self {
+        Page {
+            status: 200,
+            content_type: "text/html; charset=utf-8",
+            body,
+        }
+    }
+
+    fn text(status: u16, body: impl Into<String>) -> Self {
+        Page {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body: body.into(),
+        }
+    }
+}
+
+impl<'a> ReviewServer<'a> {
+    pub fn new(current_path: &'a Path, snapshot_path: &'a Path) -> Self {
+        let mut compare_config = CompareConfig::default();
+        compare_config.set_ignore_left_missing(true);
+        ReviewServer {
+            current_path,
+            snapshot_path,
+            compare_config,
+            token: random_token(),
+        }
+    }
+
+    pub fn run(&self, port: u16) -> crate::Result<()> {
+        let server =
+            Server::http(("127.0.0.1", port)).map_err(|e| Error::ServerError(e.to_string()))?;
+        println!("Serving report at http://127.0.0.1:{port}/ (press Ctrl+C to stop)");
+        for request in server.incoming_requests() {
+            let host = header_value(&request, "Host");
+            let token = header_value(&request, TOKEN_HEADER);
+            let page = match self.check_access(port, request.method(), host, token) {
+                Ok(()) => self
+                    .handle_request(request.method(), request.url())
+                    .unwrap_or_else(|e| {
+                        eprintln!("Error: {e}");
+                        Page::text(500, e.to_string())
+                    }),
+                Err(page) => page,
+            };
+            respond(request, page);
+        }
+        Ok(())
+    }
+
+    /// Refuses requests that may come from other pages open in the browser
+    fn check_access(
+        &self,
+        port: u16,
+        method: &Method,
+        host: Option<&str>,
+        token: Option<&str>,
+    ) -> Result<(), Page> {
+        let allowed_hosts = [format!("127.0.0.1:{port}"), format!("localhost:{port}")];
+        if !host.is_some_and(|host| allowed_hosts.iter().any(|allowed| allowed == host)) {
+            return Err(Page::text(403, "Unexpected host"));
+        }
+        if *method != Method::Get && token != Some(self.token.as_str()) {
+            return Err(Page::text(403, "Missing or invalid token"));
+        }
+        Ok(())
+    }
+
+    fn report_config(&self) -> ReportConfig<'_> {
+        let mut report_config = ReportConfig::default();
+        report_config.set_left_title("Current test");
+        report_config.set_right_title("Snapshot");
+        // The browser cannot load local files into a page served over HTTP
+        report_config.set_embed_images(true);
+        report_config.review_token = Some(&self.token);
+        report_config
+    }
+
+    fn handle_request(&self, method: &Method, url: &str) -> crate::Result<Page> {
+        let path = url.split_once('?').map_or(url, |(path, _)| path);
+        match (method, path) {
+            (Method::Get, "/") => {
+                let mut image_diff = ImageDiff::default();
+                image_diff.compare_directories(
+                    &self.compare_config,
+                    self.current_path,
+                    self.snapshot_path,
+                )?;
+                let report_config = self.report_config();
+                let mut writer = HtmlReportWriter::new(&report_config, Vec::new())?;
+                for pair_diff in &image_diff {
+                    writer.write_entry(pair_diff)?;
+                }
+                let report = writer.finish(image_diff.directories())?;
+                Ok(Page::html(
+                    String::from_utf8(report).expect("Report is valid UTF-8"),
+                ))
+            }
+            (Method::Post, "/accept") | (Method::Post, "/reject") => {
+                let Some(title) = query_param(url, "title") else {
+                    return Ok(Page::text(400, "Missing parameter 'title'"));
+                };
+                let Some(pair_diff) = self.compare_pair(&title)? else {
+                    return Ok(Page::text(404, format!("Unknown image '{title}'")));
+                };
+                if !pair_diff.is_blessable() {
+                    return Ok(Page::text(409, format!("Image '{title}' has no changes")));
+                }
+                if path == "/accept" {
+                    bless_pair(&pair_diff)?;
+                } else {
+                    std::fs::remove_file(&pair_diff.pair.left)?;
+                }
+                // The pair is looked up again, its images have just changed;
+                // a rejected image without a snapshot is gone
+                let Some(pair_diff) = self.compare_pair(&title)? else {
+                    return Ok(Page::html(render_removed_entry(&title).into_string()));
+                };
+                let entry = render_pair_diff(&self.report_config(), &pair_diff)?;
+                Ok(Page::html(entry.into_string()))
+            }
+            _ => Ok(Page::text(404, "Not found")),
+        }
+    }
+
+    /// Compares a single pair; titles are only looked up among listed pairs,
+    /// so requests cannot reach files outside of the compared directories
+    fn compare_pair(&self, title: &str) -> crate::Result<Option<PairResult>> {
+        let pair = pairs_from_paths(self.current_path, self.snapshot_path, &self.compare_config)?
+            .into_iter()
+            .find(|pair| pair.title == title);
+        pair.map(|pair| compute_pair_diff(&self.compare_config, pair))
+            .transpose()
+    }
+}
+
+fn respond(request: Request, page: Page) {
+    let header = Header::from_bytes("Content-Type", page.content_type)
+        .expect("Content type is a valid header value");
+    let response = Response::from_string(page.body)
+        .with_status_code(page.status)
+        .with_header(header);
+    if let Err(e) = request.respond(response) {
+        eprintln!("Error: cannot send response: {e}");
+    }
+}
+
+fn header_value<'r>(request: &'r Request, name: &'static str) -> Option<&'r str> {
+    request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv(name))
+        .map(|header| header.value.as_str())
+}
+
+/// Random hex token; `RandomState` is seeded from the randomness of the operating system
+fn random_token() -> String {
+    use std::collections::hash_map::RandomState;
+    use std::hash::{BuildHasher, Hasher};
+    (0..2)
+        .map(|i| {
+            let mut hasher = RandomState::new().build_hasher();
+            hasher.write_u8(i);
+            format!("{:016x}", hasher.finish())
+        })
+        .collect()
+}
+
+/// Value of a query parameter in a request URL
+fn query_param(url: &str, name: &str) -> Option<String> {
+    let (_, query) = url.split_once('?')?;
+    query.split('&').find_map(|param| {
+        let (key, value) = param.split_once('=')?;
+        (key == name).then(|| percent_decode(value))
+    })
+}
+
+fn percent_decode(value: &str) -> String {
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut i = 0;
+    while i < value.len() {
+        let byte = value.as_bytes()[i];
+        let escaped = value
+            .get(i + 1..i + 3)
+            .filter(|_| byte == b'%')
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (byte, escaped) {
+            (_, Some(escaped)) => {
+                bytes.push(escaped);
+                i += 3;
+            }
+            (b'+', None) => {
+                bytes.push(b' ');
+                i += 1;
+            }
+            (byte, None) => {
+                bytes.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn foreign_requests_are_refused() {
+        let server = ReviewServer::new(Path::new("current"), Path::new("snapshots"));
+        let token = Some(server.token.as_str());
+        let check = |method, host, token| server.check_access(8080, &method, host, token).is_ok();
+        assert!(check(Method::Get, Some("127.0.0.1:8080"), None));
+        assert!(check(Method::Post, Some("localhost:8080"), token));
+        // Cross-origin request without the token
+        assert!(!check(Method::Post, Some("127.0.0.1:8080"), None));
+        assert!(!check(Method::Post, Some("127.0.0.1:8080"), Some("guess")));
+        // DNS rebinding
+        assert!(!check(Method::Get, Some("attacker.example:8080"), None));
+        assert!(!check(Method::Post, Some("attacker.example:8080"), token));
+        assert!(!check(Method::Get, None, None));
+    }
+
+    #[test]
+    fn accept_and_reject() {
+        let dir = std::env::temp_dir().join("kompari-review-server");
+        let _ = std::fs::remove_dir_all(&dir);
+        let (current, snapshots) = (dir.join("current"), dir.join("snapshots"));
+        std::fs::create_dir_all(&current).unwrap();
+        std::fs::create_dir_all(&snapshots).unwrap();
+        for name in ["example1.png", "size_error.png"] {
+            std::fs::copy(Path::new("tests/left").join(name), current.join(name)).unwrap();
+            std::fs::copy(Path::new("tests/right").join(name), snapshots.join(name)).unwrap();
+        }
+        std::fs::copy("tests/left/right_missing.png", current.join("new.png")).unwrap();
+
+        let server = ReviewServer::new(&current, &snapshots);
+        let post = |url| server.handle_request(&Method::Post, url).unwrap();
+
+        let page = post("/accept?title=example1.png");
+        assert_eq!(page.status, 200);
+        assert!(page.body.contains("data-title=\"example1.png\""));
+        assert_eq!(
+            std::fs::read(current.join("example1.png")).unwrap(),
+            std::fs::read(snapshots.join("example1.png")).unwrap()
+        );
+        assert_eq!(post("/accept?title=example1.png").status, 409);
+
+        // The snapshot is kept, the entry shows the remaining snapshot
+        let page = post("/reject?title=size_error.png");
+        assert_eq!(page.status, 200);
+        assert!(!current.join("size_error.png").exists());
+        assert!(snapshots.join("size_error.png").exists());
+        assert!(page.body.contains("data-title=\"size_error.png\""));
+
+        // Without a snapshot, nothing of the pair is left
+        let page = post("/reject?title=new.png");
+        assert_eq!(page.status, 200);
+        assert!(!current.join("new.png").exists());
+        assert!(page.body.contains("Removed"));
+
+        assert_eq!(post("/reject?title=new.png").status, 404);
+        assert_eq!(post("/accept").status, 400);
+        assert_eq!(post("/unknown").status, 404);
+        let page = server.handle_request(&Method::Get, "/").unwrap();
+        assert_eq!(page.status, 200);
+        assert!(page.body.contains("kompari-review-token"));
+    }
+
+    #[test]
+    fn query_param_is_decoded() {
+        let url = "/accept?x=1&title=widgets%2Fbutton%20ok+2.png";
+        assert_eq!(
+            query_param(url, "title").as_deref(),
+            Some("widgets/button ok 2.png")
+        );
+        assert_eq!(query_param(url, "missing"), None);
+        assert_eq!(query_param("/accept", "title"), None);
+    }
+
+    #[test]
+    fn invalid_escapes_are_kept() {
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz%4"), "%zz%4");
+    }
+}
@@ -0,0 +1,41 @@
+// Copyright 2024 the Kompari Authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Serialization of images in results as base64 encoded PNG data.
+
+use base64::prelude::*;
+use image::{DynamicImage, ImageBuffer, ImageFormat, PixelWithColorType, RgbaImage};
+use serde::de::Error as _;
+use serde::ser::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::io::Cursor;
+
+struct Png<'a, P: PixelWithColorType<Subpixel = u8>>(&'a ImageBuffer<P, Vec<u8>>);
+
+impl<P: PixelWithColorType<Subpixel = u8>> Serialize for Png<'_, P> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut data = Vec::new();
+        self.0
+            .write_to(&mut Cursor::new(&mut data), ImageFormat::Png)
+            .map_err(S::Error::custom)?;
+        serializer.serialize_str(&BASE64_STANDARD.encode(data))
+    }
+}
+
+fn decode(value: &str) -> Result<DynamicImage, String> {
+    let data = BASE64_STANDARD.decode(value).map_err(|e| e.to_string())?;
+    image::load_from_memory_with_format(&data, ImageFormat::Png).map_err(|e| e.to_string())
+}
+
+pub(crate) mod rgba {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(image: &RgbaImage, serializer: S) -> Result<S::Ok, S::Error> {
+        Png(image).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<RgbaImage, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        Ok(decode(&value).map_err(D::Error::custom)?.into_rgba8())
+    }
+}
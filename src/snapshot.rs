@@ -0,0 +1,302 @@
+// Copyright 2024 the Kompari Authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Snapshot assertions for tests.
+//!
+//! A tested image is compared with a snapshot of the same name. When the check fails,
+//! the image is written into the directory of current images, from where it can be
+//! reviewed (e.g. with `kompari report`) and accepted (`kompari bless`).
+//!
+//! ```no_run
+//! use image::RgbImage;
+//! use kompari::snapshot::SnapshotConfig;
+//!
+//! let image = RgbImage::new(10, 10);
+//! // Snapshots in 'tests/snapshots', failed images written into 'tests/current'
+//! kompari::assert_snapshot!(image, "empty.png");
+//!
+//! let mut config = SnapshotConfig::new("snapshots", "current");
+//! config.compare_config_mut().set_pixel_tolerance(2);
+//! kompari::assert_snapshot!(config, RgbImage::new(10, 10), "empty.png");
+//! ```
+
+use crate::{compare_images, CompareConfig, ImageComparison};
+use image::DynamicImage;
+use std::path::{Path, PathBuf};
+
+/// Environment variable selecting the [`SnapshotMode`] of [`SnapshotConfig::new`]
+pub const SNAPSHOT_MODE_ENV_VAR: &str = "KOMPARI_SNAPSHOT_MODE";
+
+/// What happens with snapshots and current images during a check
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotMode {
+    /// Failed and unknown images are written into the current directory (`check`)
+    #[default]
+    Check,
+    /// As `Check`, but all images are written into the current directory,
+    /// e.g. for detecting dead snapshots (`generate-all`)
+    GenerateAll,
+    /// Snapshots of failed and unknown images are overwritten, checks do not fail (`auto-bless`)
+    AutoBless,
+    /// Missing snapshots are written, different images still fail (`record-missing`)
+    RecordMissing,
+}
+
+impl SnapshotMode {
+    /// Parses a mode name, e.g. `generate-all`; case insensitive
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "check" => Some(SnapshotMode::Check),
+            "generate-all" => Some(SnapshotMode::GenerateAll),
+            "auto-bless" => Some(SnapshotMode::AutoBless),
+            "record-missing" => Some(SnapshotMode::RecordMissing),
+            _ => None,
+        }
+    }
+}
+
+pub struct SnapshotConfig {
+    snapshot_dir: PathBuf,
+    current_dir: PathBuf,
+    mode: SnapshotMode,
+    compare_config: CompareConfig,
+}
+
+impl SnapshotConfig {
+    /// Creates a configuration in the mode given by [`SNAPSHOT_MODE_ENV_VAR`].
+    ///
+    /// Panics on an unknown mode name, so that a typo does not silently disable blessing.
+    pub fn new(snapshot_dir: impl Into<PathBuf>, current_dir: impl Into<PathBuf>) -> Self {
+        let mut config = SnapshotConfig {
+            snapshot_dir: snapshot_dir.into(),
+            current_dir: current_dir.into(),
+            mode: SnapshotMode::default(),
+            compare_config: CompareConfig::default(),
+        };
+        config.set_mode_from_env(SNAPSHOT_MODE_ENV_VAR);
+        config
+    }
+
+    /// Configuration with `tests/snapshots` and `tests/current` in the given directory,
+    /// used by [`assert_snapshot!`](crate::assert_snapshot) with the manifest directory of the tested crate
+    pub fn in_dir(dir: impl AsRef<Path>) -> Self {
+        let tests_dir = dir.as_ref().join("tests");
+        Self::new(tests_dir.join("snapshots"), tests_dir.join("current"))
+    }
+
+    pub fn set_mode(&mut self, mode: SnapshotMode) {
+        self.mode = mode;
+    }
+
+    /// Sets the mode from an environment variable (e.g. `MYLIB_TEST=generate-all`);
+    /// the mode is kept when the variable is not set
+    pub fn set_mode_from_env(&mut self, var_name: &str) {
+        if let Ok(value) = std::env::var(var_name) {
+            self.mode = SnapshotMode::from_name(&value).unwrap_or_else(|| {
+                panic!("Invalid snapshot mode '{value}' in {var_name}, expected 'check', 'generate-all', 'auto-bless' or 'record-missing'")
+            });
+        }
+    }
+
+    pub fn mode(&self) -> SnapshotMode {
+        self.mode
+    }
+
+    /// Comparison settings, e.g. tolerances
+    pub fn set_compare_config(&mut self, compare_config: CompareConfig) {
+        self.compare_config = compare_config;
+    }
+
+    pub fn compare_config_mut(&mut self) -> &mut CompareConfig {
+        &mut self.compare_config
+    }
+
+    pub fn snapshot_path(&self, name: &str) -> PathBuf {
+        self.snapshot_dir.join(name)
+    }
+
+    pub fn current_path(&self, name: &str) -> PathBuf {
+        self.current_dir.join(name)
+    }
+}
+
+/// Result of a snapshot check
+#[derive(Debug)]
+pub enum SnapshotResult {
+    /// Image matches the snapshot or the difference is within tolerance
+    Passed(ImageComparison),
+    /// The snapshot was written (in auto-bless or record-missing mode)
+    Updated,
+    /// There is no snapshot, the image was written to `current_path`
+    Missing { current_path: PathBuf },
+    /// Image differs from the snapshot, the image was written to `current_path`
+    Failed {
+        comparison: ImageComparison,
+        current_path: PathBuf,
+    },
+}
+
+impl SnapshotResult {
+    pub fn is_failure(&self) -> bool {
+        matches!(
+            self,
+            SnapshotResult::Missing { .. } | SnapshotResult::Failed { .. }
+        )
+    }
+}
+
+fn save_image(image: &DynamicImage, path: &Path) -> crate::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    image.save(path)?;
+    Ok(())
+}
+
+/// Checks an image against the snapshot `name` and writes images as given by the mode
+pub fn check_snapshot(
+    config: &SnapshotConfig,
+    image: impl Into<DynamicImage>,
+    name: &str,
+) -> crate::Result<SnapshotResult> {
+    let image = image.into();
+    let snapshot_path = config.snapshot_path(name);
+    let current_path = config.current_path(name);
+    if config.mode == SnapshotMode::GenerateAll {
+        save_image(&image, &current_path)?;
+    }
+
+    if !snapshot_path.exists() {
+        return match config.mode {
+            SnapshotMode::AutoBless | SnapshotMode::RecordMissing => {
+                save_image(&image, &snapshot_path)?;
+                Ok(SnapshotResult::Updated)
+            }
+            SnapshotMode::Check | SnapshotMode::GenerateAll => {
+                save_image(&image, &current_path)?;
+                Ok(SnapshotResult::Missing { current_path })
+            }
+        };
+    }
+
+    let snapshot = image::open(&snapshot_path)?;
+    let comparison = compare_images(&config.compare_config, &image, &snapshot);
+    if !comparison.is_failure() {
+        Ok(SnapshotResult::Passed(comparison))
+    } else if config.mode == SnapshotMode::AutoBless {
+        save_image(&image, &snapshot_path)?;
+        Ok(SnapshotResult::Updated)
+    } else {
+        save_image(&image, &current_path)?;
+        Ok(SnapshotResult::Failed {
+            comparison,
+            current_path,
+        })
+    }
+}
+
+/// Checks an image against the snapshot `name`, panics when the check fails.
+///
+/// The panic message contains statistics of the difference and the path
+/// of the written current image.
+#[track_caller]
+pub fn assert_snapshot(config: &SnapshotConfig, image: impl Into<DynamicImage>, name: &str) {
+    let result = match check_snapshot(config, image, name) {
+        Ok(result) => result,
+        Err(e) => panic!("Snapshot '{name}' cannot be checked: {e}"),
+    };
+    match result {
+        SnapshotResult::Passed(_) | SnapshotResult::Updated => {}
+        SnapshotResult::Missing { current_path } => panic!(
+            "Snapshot '{name}' is missing ({})\nCurrent image: {}",
+            config.snapshot_path(name).display(),
+            current_path.display()
+        ),
+        SnapshotResult::Failed {
+            comparison,
+            current_path,
+        } => {
+            let details = match &comparison {
+                ImageComparison::SizeMismatch { left, right } => format!(
+                    "size {}x{} differs from snapshot size {}x{}",
+                    left.0, left.1, right.0, right.1
+                ),
+                _ => comparison
+                    .content()
+                    .map(|content| content.stats_summary())
+                    .unwrap_or_default(),
+            };
+            panic!(
+                "Snapshot '{name}' is different: {details}\nCurrent image: {}\nSnapshot: {}",
+                current_path.display(),
+                config.snapshot_path(name).display()
+            )
+        }
+    }
+}
+
+/// Asserts that an image matches a snapshot, see [`snapshot`](crate::snapshot).
+///
+/// `assert_snapshot!(image, name)` uses `tests/snapshots` and `tests/current`
+/// in the manifest directory of the tested crate; `assert_snapshot!(config, image, name)`
+/// uses the given [`SnapshotConfig`].
+#[macro_export]
+macro_rules! assert_snapshot {
+    ($image:expr, $name:expr $(,)?) => {
+        $crate::snapshot::assert_snapshot(
+            &$crate::snapshot::SnapshotConfig::in_dir(env!("CARGO_MANIFEST_DIR")),
+            $image,
+            $name,
+        )
+    };
+    ($config:expr, $image:expr, $name:expr $(,)?) => {
+        $crate::snapshot::assert_snapshot(&$config, $image, $name)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    fn test_config(name: &str) -> SnapshotConfig {
+        let dir = std::env::temp_dir().join(format!("kompari-snapshot-{name}"));
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut config = SnapshotConfig::new(dir.join("snapshots"), dir.join("current"));
+        config.set_mode(SnapshotMode::Check);
+        config
+    }
+
+    #[test]
+    fn missing_and_different_snapshots() {
+        let mut config = test_config("check");
+        let image = RgbImage::from_pixel(4, 4, Rgb([10, 20, 30]));
+        let result = check_snapshot(&config, image.clone(), "a.png").unwrap();
+        assert!(matches!(result, SnapshotResult::Missing { .. }));
+        assert!(config.current_path("a.png").exists());
+
+        config.set_mode(SnapshotMode::RecordMissing);
+        let result = check_snapshot(&config, image.clone(), "a.png").unwrap();
+        assert!(matches!(result, SnapshotResult::Updated));
+        assert_snapshot!(config, image.clone(), "a.png");
+
+        let mut changed = image;
+        changed.put_pixel(0, 0, Rgb([0, 0, 0]));
+        let result = check_snapshot(&config, changed.clone(), "a.png").unwrap();
+        assert!(result.is_failure());
+
+        config
+            .compare_config_mut()
+            .set_max_different_pixels(Some(1));
+        assert_snapshot!(config, changed, "a.png");
+    }
+
+    #[test]
+    fn mode_names() {
+        assert_eq!(
+            SnapshotMode::from_name("Generate-All"),
+            Some(SnapshotMode::GenerateAll)
+        );
+        assert_eq!(SnapshotMode::from_name("bless"), None);
+    }
+}
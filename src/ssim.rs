@@ -0,0 +1,200 @@
+// Copyright 2024 the Kompari Authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Structural similarity (SSIM) of two images, computed for each color channel
+//! with the usual 11x11 Gaussian window (sigma = 1.5). The similarity of a pixel
+//! is the minimum over the channels, so that changes of hue are not missed.
+
+use image::{ImageBuffer, Luma, RgbaImage};
+
+pub(crate) type SsimMap = ImageBuffer<Luma<f32>, Vec<f32>>;
+
+const WINDOW_RADIUS: i64 = 5;
+const WINDOW_SIGMA: f32 = 1.5;
+const C1: f32 = (0.01 * 255.0) * (0.01 * 255.0);
+const C2: f32 = (0.03 * 255.0) * (0.03 * 255.0);
+
+/// Number of color channels compared
+const N_CHANNELS: usize = 3;
+/// Number of blurred quantities per channel: x, y, x², y² and xy
+const N_SUMS: usize = 5;
+
+/// Color channel of a pixel composited over black, in range 0..255
+fn channel(image: &RgbaImage, x: u32, y: u32, index: usize) -> f32 {
+    let p = image.get_pixel(x, y);
+    p.0[index] as f32 * (p.0[3] as f32 / 255.0)
+}
+
+fn gaussian_kernel() -> Vec<f32> {
+    let kernel: Vec<f32> = (-WINDOW_RADIUS..=WINDOW_RADIUS)
+        .map(|i| (-((i * i) as f32) / (2.0 * WINDOW_SIGMA * WINDOW_SIGMA)).exp())
+        .collect();
+    let sum: f32 = kernel.iter().sum();
+    kernel.into_iter().map(|v| v / sum).collect()
+}
+
+/// Index clamped into `0..len`, pixels outside the image are clamped to the border
+fn clamp(index: i64, len: usize) -> usize {
+    index.clamp(0, len as i64 - 1) as usize
+}
+
+/// Horizontally blurs all quantities of all channels of row `y` into `out`;
+/// `values` is a buffer for the quantities of one channel
+fn blur_row(
+    left: &RgbaImage,
+    right: &RgbaImage,
+    y: usize,
+    kernel: &[f32],
+    values: &mut [f32],
+    out: &mut [f32],
+) {
+    let width = left.width() as usize;
+    for c in 0..N_CHANNELS {
+        for x in 0..width {
+            let a = channel(left, x as u32, y as u32, c);
+            let b = channel(right, x as u32, y as u32, c);
+            for (i, value) in [a, b, a * a, b * b, a * b].into_iter().enumerate() {
+                values[i * width + x] = value;
+            }
+        }
+        for i in 0..N_SUMS {
+            let values = &values[i * width..(i + 1) * width];
+            let out = &mut out[(c * N_SUMS + i) * width..(c * N_SUMS + i + 1) * width];
+            for (x, out) in out.iter_mut().enumerate() {
+                *out = kernel
+                    .iter()
+                    .enumerate()
+                    .map(|(k, w)| w * values[clamp(x as i64 + k as i64 - WINDOW_RADIUS, width)])
+                    .sum();
+            }
+        }
+    }
+}
+
+/// SSIM of a pixel from the blurred quantities of its window
+fn pixel_ssim(mx: f32, my: f32, sxx: f32, syy: f32, sxy: f32) -> f32 {
+    let var_x = sxx - mx * mx;
+    let var_y = syy - my * my;
+    let cov = sxy - mx * my;
+    ((2.0 * mx * my + C1) * (2.0 * cov + C2)) / ((mx * mx + my * my + C1) * (var_x + var_y + C2))
+}
+
+/// Passes the per-pixel SSIM of each row to `f`, top to bottom.
+///
+/// The Gaussian blur is separable, so only the horizontally blurred rows of the
+/// current window are kept, memory does not grow with the height of the images.
+fn ssim_rows(left: &RgbaImage, right: &RgbaImage, mut f: impl FnMut(&[f32])) {
+    let (width, height) = (left.width() as usize, left.height() as usize);
+    let kernel = gaussian_kernel();
+    let row_len = N_CHANNELS * N_SUMS * width;
+    // Blurred rows of the window, source row `y` is kept at index `y % kernel.len()`
+    let mut window: Vec<(Option<usize>, Vec<f32>)> =
+        kernel.iter().map(|_| (None, vec![0.0; row_len])).collect();
+    let mut values = vec![0.0; N_SUMS * width];
+    let mut sums = vec![0.0; row_len];
+    let mut ssim = vec![0.0; width];
+    for y in 0..height {
+        sums.fill(0.0);
+        for (k, weight) in kernel.iter().enumerate() {
+            let source = clamp(y as i64 + k as i64 - WINDOW_RADIUS, height);
+            let (row_index, row) = &mut window[source % kernel.len()];
+            if *row_index != Some(source) {
+                blur_row(left, right, source, &kernel, &mut values, row);
+                *row_index = Some(source);
+            }
+            for (sum, value) in sums.iter_mut().zip(row.iter()) {
+                *sum += weight * value;
+            }
+        }
+        for (x, ssim) in ssim.iter_mut().enumerate() {
+            *ssim = (0..N_CHANNELS)
+                .map(|c| {
+                    let sum = |i: usize| sums[(c * N_SUMS + i) * width + x];
+                    pixel_ssim(sum(0), sum(1), sum(2), sum(3), sum(4))
+                })
+                .fold(f32::INFINITY, f32::min);
+        }
+        f(&ssim);
+    }
+}
+
+/// Returns mean SSIM of two images of the same size
+pub(crate) fn mean_ssim(left: &RgbaImage, right: &RgbaImage) -> f64 {
+    let mut sum = 0.0;
+    ssim_rows(left, right, |row| {
+        sum += row.iter().map(|v| *v as f64).sum::<f64>();
+    });
+    let n_pixels = left.width() as f64 * left.height() as f64;
+    if n_pixels == 0.0 {
+        1.0
+    } else {
+        sum / n_pixels
+    }
+}
+
+/// Returns mean SSIM of two images of the same size and the per-pixel SSIM map
+pub(crate) fn compute_ssim(left: &RgbaImage, right: &RgbaImage) -> (f64, SsimMap) {
+    let mut map = Vec::with_capacity(left.width() as usize * left.height() as usize);
+    ssim_rows(left, right, |row| map.extend_from_slice(row));
+    let mean = if map.is_empty() {
+        1.0
+    } else {
+        map.iter().map(|v| *v as f64).sum::<f64>() / map.len() as f64
+    };
+    (
+        mean,
+        SsimMap::from_vec(left.width(), left.height(), map).unwrap(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn test_ssim() {
+        let left = RgbaImage::from_fn(32, 32, |x, y| {
+            let v = ((x * 7 + y * 3) % 256) as u8;
+            Rgba([v, v, v, 255])
+        });
+        let (ssim, map) = compute_ssim(&left, &left);
+        assert!((ssim - 1.0).abs() < 1e-4);
+        assert!(map.pixels().all(|p| (p[0] - 1.0).abs() < 1e-3));
+
+        let mut right = left.clone();
+        right.put_pixel(10, 10, Rgba([255, 0, 0, 255]));
+        let (ssim, map) = compute_ssim(&left, &right);
+        assert!(ssim < 1.0);
+        assert!(map.get_pixel(10, 10)[0] < map.get_pixel(30, 30)[0]);
+    }
+
+    #[test]
+    fn mean_without_map() {
+        let left = RgbaImage::from_fn(40, 23, |x, y| Rgba([(x * 6) as u8, (y * 9) as u8, 0, 255]));
+        let right = RgbaImage::from_fn(40, 23, |x, y| {
+            Rgba([
+                (x * 6) as u8,
+                (y * 9) as u8,
+                if x > 20 { 90 } else { 0 },
+                255,
+            ])
+        });
+        let (ssim, map) = compute_ssim(&left, &right);
+        assert!(ssim < 1.0);
+        assert!((mean_ssim(&left, &right) - ssim).abs() < 1e-9);
+        assert_eq!(map.dimensions(), (40, 23));
+        // Rows near the bottom border use clamped window rows
+        assert!((map.get_pixel(5, 22)[0] - 1.0).abs() < 1e-3);
+        assert!(map.get_pixel(25, 22)[0] < 0.9);
+    }
+
+    #[test]
+    fn hue_change_lowers_ssim() {
+        // Red and green with (almost) the same luminance
+        let left = RgbaImage::from_pixel(16, 16, Rgba([255, 0, 0, 255]));
+        let right = RgbaImage::from_pixel(16, 16, Rgba([0, 130, 0, 255]));
+        let (ssim, _) = compute_ssim(&left, &right);
+        assert!(ssim < 0.1);
+    }
+}
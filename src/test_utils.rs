@@ -0,0 +1,27 @@
+// Copyright 2024 the Kompari Authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Fixtures shared by tests of the reporters.
+
+use crate::difference::{Difference, ImageInfo, ImageInfoResult, PairResult, Size};
+use crate::pair::Pair;
+
+/// Result of a pair of 10x20 images; the right image is missing for [`Difference::MissingFile`]
+pub(crate) fn pair_result(title: &str, difference: Difference) -> PairResult {
+    let info = || {
+        ImageInfoResult::Loaded(ImageInfo {
+            size: Size::new(10, 20),
+        })
+    };
+    let right_info = match difference {
+        Difference::MissingFile => ImageInfoResult::Missing,
+        _ => info(),
+    };
+    PairResult {
+        pair: Pair::new(title.into(), "left".into(), "right".into()),
+        difference,
+        left_info: info(),
+        right_info,
+        ignore_mask: None,
+    }
+}
@@ -0,0 +1,564 @@
+// Copyright 2024 the Kompari Authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+use crate::bless::bless_pair;
+use crate::difference::{load_image, Difference, ImageInfoResult, PairResult};
+use crate::report::encode_png;
+use crate::{ReportConfig, TerminalGraphics};
+use base64::prelude::*;
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::style::{
+    Attribute, Color, Print, ResetColor, SetAttribute, SetBackgroundColor, SetForegroundColor,
+};
+use crossterm::terminal::{
+    BeginSynchronizedUpdate, Clear, ClearType, EndSynchronizedUpdate, EnterAlternateScreen,
+    LeaveAlternateScreen,
+};
+use crossterm::{execute, queue};
+use image::imageops::FilterType;
+use image::RgbaImage;
+use std::collections::BTreeSet;
+use std::io::{BufWriter, Write};
+
+/// Width of the list of image pairs in columns
+const LIST_WIDTH: u16 = 32;
+/// Number of lines above image previews: header, title, stats, review state and labels
+const DETAIL_HEADER_HEIGHT: u16 = 5;
+const MIN_WIDTH: u16 = 60;
+const MIN_HEIGHT: u16 = 10;
+/// Maximal size of a payload chunk in the kitty graphics protocol
+const KITTY_CHUNK_SIZE: usize = 4096;
+/// Size of a character cell in pixels when the terminal does not report it
+const DEFAULT_CELL_SIZE: (u32, u32) = (10, 20);
+const HELP: &str = "a accept  s skip  n next failure  \u{2191}/\u{2193} select  q quit";
+
+/// Switches the terminal into the raw mode and back when dropped, even on error
+struct TerminalGuard;
+
+impl TerminalGuard {
+    fn enter() -> std::io::Result<Self> {
+        crossterm::terminal::enable_raw_mode()?;
+        execute!(std::io::stdout(), EnterAlternateScreen, Hide)?;
+        Ok(TerminalGuard)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = execute!(std::io::stdout(), Show, LeaveAlternateScreen);
+        let _ = crossterm::terminal::disable_raw_mode();
+    }
+}
+
+/// Terminals known to implement the kitty graphics protocol
+fn detect_kitty_graphics() -> bool {
+    let term = std::env::var("TERM").unwrap_or_default();
+    let term_program = std::env::var("TERM_PROGRAM").unwrap_or_default();
+    std::env::var_os("KITTY_WINDOW_ID").is_some()
+        || term.contains("kitty")
+        || term_program == "WezTerm"
+        || term_program == "ghostty"
+}
+
+struct Review<'a> {
+    diffs: &'a [PairResult],
+    left_title: &'a str,
+    right_title: &'a str,
+    kitty_graphics: bool,
+    selected: usize,
+    /// Indices of accepted pairs
+    accepted: BTreeSet<usize>,
+    message: String,
+    /// Left, right and difference image of the pair with the given index
+    previews: Option<(usize, [Option<RgbaImage>; 3])>,
+    /// Previews encoded for the kitty graphics protocol
+    kitty_images: Option<KittyPreviews>,
+}
+
+/// Encoded previews of a pair, for the pair index and the preview size in cells
+struct KittyPreviews {
+    key: (usize, u16, u16),
+    images: Vec<Option<KittyImage>>,
+}
+
+/// Image downscaled to the cells it is shown in and encoded as base64 PNG
+struct KittyImage {
+    cols: u16,
+    rows: u16,
+    data: String,
+}
+
+impl Review<'_> {
+    fn handle_key(&mut self, key: KeyEvent) -> crate::Result<bool> {
+        self.message.clear();
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(false),
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                return Ok(false)
+            }
+            KeyCode::Char('a') => self.accept()?,
+            KeyCode::Char('s') | KeyCode::Char('j') | KeyCode::Down | KeyCode::Right => {
+                self.selected = (self.selected + 1).min(self.diffs.len() - 1);
+            }
+            KeyCode::Char('k') | KeyCode::Up | KeyCode::Left => {
+                self.selected = self.selected.saturating_sub(1);
+            }
+            KeyCode::Home => self.selected = 0,
+            KeyCode::End => self.selected = self.diffs.len() - 1,
+            KeyCode::Char('n') | KeyCode::Tab => {
+                let found = self.select_next_failure();
+                self.message = if found { "" } else { "No more failures" }.to_string();
+            }
+            _ => {}
+        }
+        Ok(true)
+    }
+
+    fn accept(&mut self) -> crate::Result<()> {
+        let pair_diff = &self.diffs[self.selected];
+        let title = &pair_diff.pair.title;
+        if self.accepted.contains(&self.selected) {
+            self.message = format!("{title} is already accepted");
+        } else if !pair_diff.is_blessable() {
+            self.message = format!("Nothing to accept in {title}");
+        } else {
+            bless_pair(pair_diff)?;
+            self.accepted.insert(self.selected);
+            self.message = format!("Accepted {title}");
+            if !self.select_next_failure() {
+                self.message.push_str(", no more failures");
+            }
+        }
+        Ok(())
+    }
+
+    /// Selects the next failure that is not accepted yet, wrapping around
+    fn select_next_failure(&mut self) -> bool {
+        let n = self.diffs.len();
+        let next = (1..=n)
+            .map(|offset| (self.selected + offset) % n)
+            .find(|&i| self.diffs[i].difference.is_failure() && !self.accepted.contains(&i));
+        if let Some(next) = next {
+            self.selected = next;
+        }
+        next.is_some()
+    }
+
+    fn update_previews(&mut self) {
+        if matches!(&self.previews, Some((index, _)) if *index == self.selected) {
+            return;
+        }
+        let pair_diff = &self.diffs[self.selected];
+        let load = |info: &ImageInfoResult, path| info.info().and_then(|_| load_image(path).ok());
+        let diff_image = pair_diff
+            .difference
+            .content()
+            .and_then(|content| content.diff_image.load().ok())
+            .map(|image| image.into_owned());
+        self.previews = Some((
+            self.selected,
+            [
+                load(&pair_diff.left_info, &pair_diff.pair.left),
+                load(&pair_diff.right_info, &pair_diff.pair.right),
+                diff_image,
+            ],
+        ));
+    }
+
+    /// Encodes previews of the selected pair unless they are encoded for the given size
+    fn update_kitty_images(&mut self, cols: u16, rows: u16) {
+        let key = (self.selected, cols, rows);
+        if matches!(&self.kitty_images, Some(cached) if cached.key == key) {
+            return;
+        }
+        let Some((_, previews)) = &self.previews else {
+            return;
+        };
+        let cell_size = cell_size();
+        let images = previews
+            .iter()
+            .map(|preview| {
+                let image = preview.as_ref()?;
+                Some(KittyImage::new(image, cols, rows, cell_size))
+            })
+            .collect();
+        self.kitty_images = Some(KittyPreviews { key, images });
+    }
+
+    fn draw(&mut self, out: &mut impl Write) -> crate::Result<()> {
+        let (cols, rows) = crossterm::terminal::size()?;
+        queue!(out, BeginSynchronizedUpdate, Clear(ClearType::All))?;
+        if self.kitty_graphics {
+            // Delete images placed by the previous draw
+            queue!(out, Print("\x1b_Ga=d,q=2\x1b\\"))?;
+        }
+        if cols < MIN_WIDTH || rows < MIN_HEIGHT {
+            queue!(out, MoveTo(0, 0), Print("Terminal is too small"))?;
+        } else {
+            self.update_previews();
+            let (detail_x, detail_width, detail_height) =
+                (LIST_WIDTH + 1, cols - LIST_WIDTH - 1, rows - 2);
+            if self.kitty_graphics {
+                let (column_width, preview_height) = preview_size(detail_width, detail_height);
+                self.update_kitty_images(column_width, preview_height);
+            }
+            let header = format!(
+                " Kompari review: {}/{}, {} accepted",
+                self.selected + 1,
+                self.diffs.len(),
+                self.accepted.len()
+            );
+            queue!(
+                out,
+                MoveTo(0, 0),
+                SetAttribute(Attribute::Reverse),
+                Print(fit_text(&header, cols)),
+                SetAttribute(Attribute::Reset),
+            )?;
+            self.draw_list(out, rows - 2)?;
+            self.draw_detail(out, detail_x, detail_width, detail_height)?;
+            let footer = format!("{HELP}  {}", self.message);
+            queue!(out, MoveTo(0, rows - 1), Print(fit_text(&footer, cols)))?;
+        }
+        queue!(out, EndSynchronizedUpdate)?;
+        out.flush()?;
+        Ok(())
+    }
+
+    fn draw_list(&self, out: &mut impl Write, height: u16) -> crate::Result<()> {
+        let height = usize::from(height);
+        let first = self
+            .selected
+            .saturating_sub(height / 2)
+            .min(self.diffs.len().saturating_sub(height));
+        for (row, (i, pair_diff)) in self
+            .diffs
+            .iter()
+            .enumerate()
+            .skip(first)
+            .take(height)
+            .enumerate()
+        {
+            let marker = if self.accepted.contains(&i) {
+                '\u{2713}'
+            } else {
+                status_marker(&pair_diff.difference)
+            };
+            let line = format!("{marker} {}", pair_diff.pair.title);
+            queue!(
+                out,
+                MoveTo(0, row as u16 + 1),
+                SetForegroundColor(status_color(&pair_diff.difference))
+            )?;
+            if i == self.selected {
+                queue!(out, SetAttribute(Attribute::Reverse))?;
+            }
+            queue!(
+                out,
+                Print(fit_text(&line, LIST_WIDTH - 1)),
+                SetAttribute(Attribute::Reset),
+                ResetColor
+            )?;
+        }
+        Ok(())
+    }
+
+    fn draw_detail(
+        &self,
+        out: &mut impl Write,
+        x: u16,
+        width: u16,
+        height: u16,
+    ) -> crate::Result<()> {
+        let pair_diff = &self.diffs[self.selected];
+        queue!(
+            out,
+            MoveTo(x, 1),
+            SetAttribute(Attribute::Bold),
+            Print(fit_text(&pair_diff.pair.title, width)),
+            SetAttribute(Attribute::Reset),
+            MoveTo(x, 2),
+            SetForegroundColor(status_color(&pair_diff.difference)),
+            Print(fit_text(&pair_diff.description(), width)),
+            ResetColor,
+        )?;
+        if self.accepted.contains(&self.selected) {
+            let note = format!("Accepted, {} replaced", self.right_title);
+            queue!(
+                out,
+                MoveTo(x, 3),
+                SetForegroundColor(Color::Green),
+                Print(fit_text(&note, width)),
+                ResetColor
+            )?;
+        }
+
+        let (column_width, preview_height) = preview_size(width, height);
+        let labels = [self.left_title, self.right_title, "Difference"];
+        let Some((_, previews)) = &self.previews else {
+            return Ok(());
+        };
+        for (i, (label, preview)) in labels.iter().zip(previews).enumerate() {
+            let column_x = x + i as u16 * (column_width + 1);
+            queue!(
+                out,
+                MoveTo(column_x, DETAIL_HEADER_HEIGHT - 1),
+                SetAttribute(Attribute::Underlined),
+                Print(fit_text(label, column_width)),
+                SetAttribute(Attribute::Reset),
+            )?;
+            let kitty_image = self
+                .kitty_images
+                .as_ref()
+                .and_then(|previews| previews.images.get(i)?.as_ref());
+            match preview {
+                Some(_) if self.kitty_graphics => {
+                    if let Some(kitty_image) = kitty_image {
+                        draw_kitty_image(out, kitty_image, column_x, DETAIL_HEADER_HEIGHT)?;
+                    }
+                }
+                Some(image) => draw_half_blocks(
+                    out,
+                    image,
+                    column_x,
+                    DETAIL_HEADER_HEIGHT,
+                    column_width,
+                    preview_height,
+                )?,
+                None => queue!(
+                    out,
+                    MoveTo(column_x, DETAIL_HEADER_HEIGHT),
+                    Print(fit_text("N/A", column_width))
+                )?,
+            }
+        }
+        Ok(())
+    }
+}
+
+fn status_marker(difference: &Difference) -> char {
+    match difference {
+        Difference::None => '=',
+        Difference::WithinTolerance(_) => '~',
+        Difference::Content(_) => '\u{2260}',
+        Difference::MissingFile | Difference::LoadError(_) | Difference::SizeMismatch => '!',
+    }
+}
+
+fn status_color(difference: &Difference) -> Color {
+    match difference {
+        Difference::None => Color::Green,
+        Difference::WithinTolerance(_) => Color::Cyan,
+        Difference::Content(_) => Color::Yellow,
+        Difference::MissingFile | Difference::LoadError(_) | Difference::SizeMismatch => Color::Red,
+    }
+}
+
+/// Truncates or pads text to exactly `width` characters
+fn fit_text(text: &str, width: u16) -> String {
+    let width = usize::from(width);
+    let mut text: String = text.chars().take(width).collect();
+    let len = text.chars().count();
+    text.extend(std::iter::repeat(' ').take(width - len));
+    text
+}
+
+/// Largest size with the aspect ratio of `width` x `height` fitting into the maximal size
+fn fit_size(width: u32, height: u32, max_width: u16, max_height: u16) -> (u16, u16) {
+    let scale = (f64::from(max_width) / f64::from(width.max(1)))
+        .min(f64::from(max_height) / f64::from(height.max(1)));
+    let fit = |size: u32, max: u16| ((f64::from(size) * scale).round() as u16).clamp(1, max);
+    (fit(width, max_width), fit(height, max_height))
+}
+
+/// Color of a pixel; transparent pixels are blended over a checkerboard
+fn cell_color(image: &RgbaImage, x: u16, y: u16) -> Color {
+    let [r, g, b, a] = image.get_pixel(x.into(), y.into()).0;
+    let backdrop: u32 = if (x / 2 + y / 4) % 2 == 0 { 0xd4 } else { 0xff };
+    let blend =
+        |c: u8| ((u32::from(c) * u32::from(a) + backdrop * (255 - u32::from(a))) / 255) as u8;
+    Color::Rgb {
+        r: blend(r),
+        g: blend(g),
+        b: blend(b),
+    }
+}
+
+/// Draws an image with "upper half block" characters, two pixels per character cell
+fn draw_half_blocks(
+    out: &mut impl Write,
+    image: &RgbaImage,
+    x: u16,
+    y: u16,
+    cols: u16,
+    rows: u16,
+) -> crate::Result<()> {
+    let (width, height) = fit_size(image.width(), image.height(), cols, rows * 2);
+    let scaled = image::imageops::resize(image, width.into(), height.into(), FilterType::Nearest);
+    for row in 0..height.div_ceil(2) {
+        queue!(out, MoveTo(x, y + row))?;
+        for col in 0..width {
+            let top = cell_color(&scaled, col, 2 * row);
+            let bottom = if 2 * row + 1 < height {
+                cell_color(&scaled, col, 2 * row + 1)
+            } else {
+                Color::Reset
+            };
+            queue!(
+                out,
+                SetForegroundColor(top),
+                SetBackgroundColor(bottom),
+                Print('\u{2580}')
+            )?;
+        }
+        queue!(out, ResetColor)?;
+    }
+    Ok(())
+}
+
+/// Width of a preview column and height of previews in cells
+/// for a detail area of the given size
+fn preview_size(width: u16, height: u16) -> (u16, u16) {
+    ((width - 2) / 3, height - DETAIL_HEADER_HEIGHT + 1)
+}
+
+/// Size of a character cell in pixels
+fn cell_size() -> (u32, u32) {
+    match crossterm::terminal::window_size() {
+        Ok(size) if size.width > 0 && size.height > 0 && size.columns > 0 && size.rows > 0 => (
+            u32::from(size.width / size.columns).max(1),
+            u32::from(size.height / size.rows).max(1),
+        ),
+        _ => DEFAULT_CELL_SIZE,
+    }
+}
+
+impl KittyImage {
+    /// Large images are downscaled to the pixels of the cells, so that only
+    /// a small PNG has to be encoded and sent to the terminal
+    fn new(image: &RgbaImage, max_cols: u16, max_rows: u16, cell_size: (u32, u32)) -> Self {
+        // Character cells are assumed to be twice as high as wide
+        let (cols, rows) = fit_size(
+            image.width(),
+            image.height().div_ceil(2),
+            max_cols,
+            max_rows,
+        );
+        let (max_width, max_height) =
+            (u32::from(cols) * cell_size.0, u32::from(rows) * cell_size.1);
+        let data = if image.width() > max_width || image.height() > max_height {
+            let (width, height) = fit_size(
+                image.width(),
+                image.height(),
+                max_width.min(u32::from(u16::MAX)) as u16,
+                max_height.min(u32::from(u16::MAX)) as u16,
+            );
+            let scaled =
+                image::imageops::resize(image, width.into(), height.into(), FilterType::Triangle);
+            encode_png(&scaled)
+        } else {
+            encode_png(image)
+        };
+        KittyImage {
+            cols,
+            rows,
+            data: BASE64_STANDARD.encode(data),
+        }
+    }
+}
+
+/// Draws an image with the kitty graphics protocol, the terminal scales it into the cells
+fn draw_kitty_image(out: &mut impl Write, image: &KittyImage, x: u16, y: u16) -> crate::Result<()> {
+    let KittyImage { cols, rows, data } = image;
+    let chunks: Vec<_> = data.as_bytes().chunks(KITTY_CHUNK_SIZE).collect();
+    queue!(out, MoveTo(x, y))?;
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = u8::from(i + 1 < chunks.len());
+        let chunk = std::str::from_utf8(chunk).expect("Base64 data is ASCII");
+        // 'q=2' suppresses responses of the terminal which would arrive as key events
+        let command = if i == 0 {
+            format!("\x1b_Ga=T,f=100,c={cols},r={rows},C=1,q=2,m={more};{chunk}\x1b\\")
+        } else {
+            format!("\x1b_Gm={more};{chunk}\x1b\\")
+        };
+        queue!(out, Print(command))?;
+    }
+    Ok(())
+}
+
+/// Runs the interactive review, returns titles of accepted pairs
+pub(crate) fn review(
+    config: &ReportConfig,
+    diffs: &[PairResult],
+    graphics: TerminalGraphics,
+) -> crate::Result<Vec<String>> {
+    let mut review = Review {
+        diffs,
+        left_title: config.left_title,
+        right_title: config.right_title,
+        kitty_graphics: match graphics {
+            TerminalGraphics::Auto => detect_kitty_graphics(),
+            TerminalGraphics::Kitty => true,
+            TerminalGraphics::HalfBlocks => false,
+        },
+        selected: 0,
+        accepted: BTreeSet::new(),
+        message: String::new(),
+        previews: None,
+        kitty_images: None,
+    };
+    if !diffs[0].difference.is_failure() {
+        review.select_next_failure();
+    }
+
+    let _guard = TerminalGuard::enter()?;
+    let mut out = BufWriter::new(std::io::stdout());
+    loop {
+        review.draw(&mut out)?;
+        if let Event::Key(key) = crossterm::event::read()? {
+            if key.kind == KeyEventKind::Press && !review.handle_key(key)? {
+                break;
+            }
+        }
+    }
+    Ok(review
+        .accepted
+        .iter()
+        .map(|&i| diffs[i].pair.title.clone())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fit_size_keeps_aspect_ratio() {
+        assert_eq!(fit_size(100, 50, 20, 20), (20, 10));
+        assert_eq!(fit_size(10, 40, 20, 20), (5, 20));
+        // Small images are scaled up
+        assert_eq!(fit_size(2, 2, 10, 20), (10, 10));
+    }
+
+    #[test]
+    fn kitty_images_are_downscaled_to_cells() {
+        let decoded_size = |image: &KittyImage| {
+            let data = BASE64_STANDARD.decode(&image.data).unwrap();
+            let decoded = image::load_from_memory(&data).unwrap();
+            (decoded.width(), decoded.height())
+        };
+        let large = KittyImage::new(&RgbaImage::new(2000, 1000), 20, 10, (10, 20));
+        assert_eq!((large.cols, large.rows), (20, 5));
+        assert_eq!(decoded_size(&large), (200, 100));
+        // Small images are sent as they are, the terminal scales them
+        let small = KittyImage::new(&RgbaImage::new(4, 2), 20, 10, (10, 20));
+        assert_eq!(decoded_size(&small), (4, 2));
+    }
+
+    #[test]
+    fn fit_text_pads_and_truncates() {
+        assert_eq!(fit_text("abc", 5), "abc  ");
+        assert_eq!(fit_text("\u{2713} abcdef", 4), "\u{2713} ab");
+    }
+}
@@ -2,7 +2,8 @@
 // SPDX-License-Identifier: Apache-2.0 OR MIT
 
 use crate::fs::{list_image_dir, list_image_dir_names};
-use clap::Parser;
+use crate::review_server::ReviewServer;
+use clap::{ArgGroup, Parser};
 use std::collections::BTreeSet;
 use std::path::{Path, PathBuf};
 
@@ -18,6 +19,10 @@ pub enum XtaskCommand {
     Report(ReportArgs),
     Clean,
     DeadSnapshots(DeadSnapshotArgs),
+    /// Replace snapshots by current test results
+    Bless(BlessArgs),
+    /// Serve the report on localhost, with buttons accepting or rejecting changes
+    Serve(ServeArgs),
 }
 
 #[derive(Parser, Debug)]
@@ -37,6 +42,28 @@ pub struct DeadSnapshotArgs {
     remove_files: bool,
 }
 
+#[derive(Parser, Debug)]
+#[command(group(ArgGroup::new("selection").required(true).multiple(true)))]
+pub struct BlessArgs {
+    /// File with accepted images, one per line (downloaded from the HTML report)
+    #[arg(long, group = "selection")]
+    accept_list: Option<PathBuf>,
+
+    /// Accept images matching a pattern, may be used multiple times.
+    /// Patterns without glob metacharacters match any part of the name, patterns with them
+    /// (or prefixed with 'glob:') match the whole name; patterns prefixed with 're:' are
+    /// regular expressions
+    #[arg(long, group = "selection")]
+    filter: Vec<String>,
+}
+
+#[derive(Parser, Debug)]
+pub struct ServeArgs {
+    /// Port of the server on localhost
+    #[arg(long, default_value_t = 8080)]
+    port: u16,
+}
+
 pub trait XtaskActions {
     fn generate_all_tests(&self) -> crate::Result<()>;
 }
@@ -58,6 +85,12 @@ impl XtaskArgs {
             XtaskCommand::DeadSnapshots(ds_args) => {
                 process_dead_snapshots(current_path, snapshots_path, actions, ds_args)?;
             }
+            XtaskCommand::Bless(bless_args) => {
+                bless_snapshots(current_path, snapshots_path, bless_args)?;
+            }
+            XtaskCommand::Serve(serve_args) => {
+                ReviewServer::new(current_path, snapshots_path).run(serve_args.port)?;
+            }
         }
         Ok(())
     }
@@ -121,13 +154,55 @@ fn create_report(
     let mut config = crate::CompareConfig::default();
     config.set_ignore_left_missing(true);
 
-    let mut image_diff = crate::ImageDiff::default();
-    image_diff.compare_directories(&config, current_path, snapshot_path)?;
-
     let mut report_config = crate::ReportConfig::default();
     report_config.set_left_title("Current test");
     report_config.set_right_title("Snapshot");
     report_config.set_embed_images(report_config.embed_images);
-    image_diff.create_report(&report_config, &report_args.output, true)?;
+
+    let output = &report_args.output;
+    let mut writer = crate::HtmlReportWriter::create(&report_config, output)?;
+    let mut count = 0;
+    crate::compare_directories_with(&config, current_path, snapshot_path, |pair_diff| {
+        count += 1;
+        writer.write_entry(&pair_diff)
+    })?;
+    writer.finish(&[(current_path.to_path_buf(), snapshot_path.to_path_buf())])?;
+    if count == 0 {
+        std::fs::remove_file(output)?;
+        println!("Nothing to report");
+    } else {
+        println!(
+            "Report written into '{}'; found {} images",
+            output.display(),
+            count
+        );
+    }
+    Ok(())
+}
+
+fn bless_snapshots(
+    current_path: &Path,
+    snapshot_path: &Path,
+    bless_args: &BlessArgs,
+) -> crate::Result<()> {
+    let mut config = crate::CompareConfig::default();
+    config.set_ignore_left_missing(true);
+    for pattern in &bless_args.filter {
+        config.add_include_pattern(pattern)?;
+    }
+
+    let mut image_diff = crate::ImageDiff::default();
+    image_diff.compare_directories(&config, current_path, snapshot_path)?;
+
+    let outcome = if let Some(accept_list) = &bless_args.accept_list {
+        image_diff.bless_accept_list(accept_list)?
+    } else {
+        crate::BlessOutcome {
+            blessed: image_diff.bless(|_| true)?,
+            skipped: Vec::new(),
+        }
+    };
+    print!("{outcome}");
+    println!("{} snapshot(s) updated", outcome.blessed.len());
     Ok(())
 }